@@ -1,12 +1,15 @@
+mod repl;
+mod runtime;
+mod summary;
+
 use chrono::Local;
-use clap::Parser;
-use lumo_core::{Compiler, Op, Type};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use lumo_core::{Check, Compiler, Gc, format};
 use sha2::{Digest, Sha256};
 use std::{
-    env::{current_dir, set_current_dir},
-    fs::{File, read_to_string},
-    io::Write,
-    path::Path,
+    fs::{read_to_string, write},
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 
 #[derive(Parser)]
@@ -15,76 +18,164 @@ use std::{
     about = "A programming language that will be compiled to WebAssembly"
 )]
 struct Cli {
-    /// Source code file path
-    path: String,
-    /// Show type inference summary
-    #[arg(long = "summary", short = 's')]
-    summary: bool,
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn main() {
-    let cli = Cli::parse();
-    let mut compiler = Compiler::new();
-    let filename = Path::new(&cli.path);
+#[derive(Subcommand)]
+enum Command {
+    /// Compile the source code file into WebAssembly
+    Build {
+        /// Source code file path
+        path: String,
+        /// Output file path, where the extension is replaced for each format
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Format of the output files
+        #[arg(long, value_enum, default_value_t = Emit::Both)]
+        emit: Emit,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Compile and run the source code file, and print its result
+    Run {
+        /// Source code file path
+        path: String,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Start an interactive session that keeps definitions between lines
+    Repl,
+    /// Type check the source code file without writing output files
+    Check {
+        /// Source code file path
+        path: String,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Show the type inference summary
+    Summary {
+        /// Source code file path
+        path: String,
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Rewrite the source code file in the canonical style
+    Fmt {
+        /// Source code file path
+        path: String,
+        /// Only check whether the file is formatted, without rewriting it
+        #[arg(long)]
+        check: bool,
+    },
+}
 
-    let Ok(source) = read_to_string(filename) else {
-        eprintln!("Failed to read source file");
-        return;
-    };
-    let original_dir = current_dir().unwrap();
-    set_current_dir(Path::new(filename).parent().unwrap()).unwrap();
-    let Some(wat_code) = compiler.build(&source) else {
-        let error_message = "failed to parse, compile or type check";
-        let err = compiler.error.unwrap_or(error_message.to_owned());
-        eprintln!("Error: {err}");
-        return;
-    };
-    if cli.summary {
-        println!("# Type Inference Summary");
-        println!("Functions:");
-        compiler.export.extend(compiler.function.clone());
-        for (name, func) in &compiler.export {
-            println!(
-                " - {name}({}): {}",
-                func.arguments
-                    .iter()
-                    .map(|(name, arg)| {
-                        let typ = arg.restore_alias(&compiler).format();
-                        format!("{name}: {typ}")
-                    })
-                    .collect::<Vec<_>>()
-                    .join(", "),
-                func.returns.restore_alias(&compiler).format()
-            );
-        }
-        println!("Overloads:");
-        let table = Op::overload_id_table();
-        for (function, (op, (lhs, rhs))) in &compiler.overload {
-            let op = table.iter().find(|&(_, id)| id == op).unwrap().0;
-            println!(" - {function}: {} {op} {}", lhs.format(), rhs.format());
-        }
-        println!("Variables:");
-        for (name, typ) in &compiler.variable {
-            println!(" - {name}: {}", typ.restore_alias(&compiler).format());
-        }
-        println!("Globals:");
-        for (name, typ) in &compiler.global {
-            println!(" - {name}: {}", typ.restore_alias(&compiler).format());
-        }
-        println!("Aliases:");
-        for (name, typ) in &compiler.alias {
-            if *name != Type::Any.format() {
-                println!(" - {name}: {}", typ.format());
-            }
+/// Memory of the module and the checks that it does at runtime
+#[derive(Args)]
+struct Options {
+    /// Page count of the memory at the start, in 64 KiB pages
+    #[arg(long, default_value_t = 64)]
+    initial_pages: u32,
+    /// Page count that the memory can grow up to, unlimited if not given
+    #[arg(long)]
+    max_pages: Option<u32>,
+    /// Strategy to reclaim heap objects that are no longer used
+    #[arg(long, value_enum, default_value_t = Collector::None)]
+    gc: Collector,
+    /// Runtime checks to emit, separated by commas
+    #[arg(long, value_enum, value_delimiter = ',')]
+    checks: Vec<Checks>,
+}
+
+impl Options {
+    fn compiler(&self) -> Compiler {
+        let mut compiler = Compiler::new();
+        compiler.initial_pages = self.initial_pages;
+        compiler.max_pages = self.max_pages;
+        compiler.gc = match self.gc {
+            Collector::None => Gc::None,
+            Collector::Rc => Gc::Rc,
+            Collector::MarkSweep => Gc::MarkSweep,
+            Collector::Wasm => Gc::Wasm,
+        };
+        for check in &self.checks {
+            compiler.checks.insert(match check {
+                Checks::Bounds => Check::Bounds,
+                Checks::Null => Check::Null,
+            });
         }
-        println!("Module:");
-        for (name, (args, _)) in &compiler.module {
-            println!(" - {name}({})", args.join(", "));
+        compiler
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Collector {
+    /// Objects are only freed explicitly
+    None,
+    /// Reference counting
+    Rc,
+    /// Tracing garbage collection, which also reclaims cyclic structures
+    MarkSweep,
+    /// Structs and arrays of the Wasm GC proposal, which the host engine manages
+    Wasm,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Checks {
+    /// Array indexes are in `-len..len`, instead of wrapped by the length
+    Bounds,
+    /// Fields and elements of null trap, instead of reading an invalid address
+    Null,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Emit {
+    Wat,
+    Wasm,
+    Both,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Build {
+            path,
+            output,
+            emit,
+            options,
+        } => build(&path, output, emit, &options),
+        Command::Run { path, options } => run(&path, &options),
+        Command::Repl => repl::start(),
+        Command::Check { path, options } => check(&path, &options),
+        Command::Summary {
+            path,
+            format,
+            options,
+        } => summary(&path, format, &options),
+        Command::Fmt { path, check } => fmt(&path, check),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("Error: {message}");
+            ExitCode::FAILURE
         }
-        let returns = compiler.result.restore_alias(&compiler).format();
-        println!("Returns: {returns}");
     }
+}
 
+fn build(path: &str, output: Option<PathBuf>, emit: Emit, options: &Options) -> Result<(), String> {
+    let source = read_source(path)?;
+    let mut compiler = options.compiler();
+    let Some(wat_code) = compiler.build(&source) else {
+        return Err(report(&compiler, &source, path));
+    };
     let wat_code = format!(
         ";; Generated by Lumo compiler on {}\n;; Source Hash: {:x}\n{wat_code}",
         Local::now().format("%Y/%m/%d %H:%M:%S"),
@@ -95,25 +186,95 @@ fn main() {
         }
     );
 
-    set_current_dir(original_dir).unwrap();
-    let Ok(mut output_file) = File::create(filename.with_extension("wat")) else {
-        eprintln!("Failed to create output WAT file");
-        return;
-    };
-    let Ok(_) = output_file.write_all(wat_code.as_bytes()) else {
-        eprintln!("Failed to write output in the WAT file");
-        return;
-    };
-    let Ok(binary) = wat::parse_str(wat_code) else {
-        eprintln!("Failed to compile WAT file");
-        return;
-    };
-    let Ok(mut output_file) = File::create(filename.with_extension("wasm")) else {
-        eprintln!("Failed to create output WASM file");
-        return;
+    let output = output.unwrap_or_else(|| Path::new(path).to_path_buf());
+    if emit != Emit::Wasm {
+        let file = output.with_extension("wat");
+        write(&file, &wat_code)
+            .map_err(|err| format!("failed to write {}: {err}", file.display()))?;
+    }
+    if emit != Emit::Wat {
+        let binary = wat::parse_str(wat_code)
+            .map_err(|err| format!("failed to assemble generated code: {err}"))?;
+        let file = output.with_extension("wasm");
+        write(&file, binary).map_err(|err| format!("failed to write {}: {err}", file.display()))?;
+    }
+    Ok(())
+}
+
+fn run(path: &str, options: &Options) -> Result<(), String> {
+    if let Collector::Wasm = options.gc {
+        let note = "build the module and run it on an engine that supports it";
+        return Err(format!(
+            "the runtime doesn't support the Wasm GC proposal, {note}"
+        ));
+    }
+    let source = read_source(path)?;
+    let mut compiler = options.compiler();
+    let Some(wat_code) = compiler.build(&source) else {
+        return Err(report(&compiler, &source, path));
     };
-    let Ok(_) = output_file.write_all(&binary) else {
-        eprintln!("Failed to write generated binary in the WASM file");
-        return;
+    let binary = wat::parse_str(wat_code)
+        .map_err(|err| format!("failed to assemble generated code: {err}"))?;
+    if let Some(result) = runtime::run(&binary, &compiler)? {
+        println!("{result}");
+    }
+    Ok(())
+}
+
+fn check(path: &str, options: &Options) -> Result<(), String> {
+    let source = read_source(path)?;
+    let mut compiler = options.compiler();
+    match compiler.parse(&source).and_then(|ast| compiler.check(&ast)) {
+        Some(()) => Ok(()),
+        None => Err(report(&compiler, &source, path)),
+    }
+}
+
+fn summary(path: &str, format: Format, options: &Options) -> Result<(), String> {
+    let source = read_source(path)?;
+    let mut compiler = options.compiler();
+    // Environments are filled in compilation, not only in type checking
+    if compiler.build(&source).is_none() {
+        return Err(report(&compiler, &source, path));
+    }
+    let summary = compiler.summary();
+    match format {
+        Format::Text => summary::print_text(&summary)?,
+        Format::Json => summary::print_json(&summary)?,
+    }
+    Ok(())
+}
+
+fn fmt(path: &str, check: bool) -> Result<(), String> {
+    let source = read_source(path)?;
+    let code = match format(&source) {
+        Ok(code) => code,
+        Err(errors) => {
+            for err in &errors {
+                eprintln!("{}\n", err.render(&source, path));
+            }
+            let count = errors.len();
+            return Err(format!("failed to format due to {count} previous error(s)"));
+        }
     };
+    if code == source {
+        return Ok(());
+    }
+    if check {
+        return Err(format!("{path} is not formatted"));
+    }
+    write(path, code).map_err(|err| format!("failed to write {path}: {err}"))
+}
+
+fn read_source(path: &str) -> Result<String, String> {
+    read_to_string(path).map_err(|err| format!("failed to read {path}: {err}"))
+}
+
+/// Print the diagnostics of the compiler, and return the message to exit with
+fn report(compiler: &Compiler, source: &str, path: &str) -> String {
+    for err in &compiler.errors {
+        eprintln!("{}\n", err.render(source, path));
+    }
+    let count = compiler.errors.len();
+    format!("failed to compile due to {count} previous error(s)")
 }
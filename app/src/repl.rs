@@ -0,0 +1,201 @@
+use crate::{runtime::Program, summary};
+use lumo_core::{Compiler, Expr, Scope, Stmt, Type};
+use rustyline::{DefaultEditor, error::ReadlineError};
+use std::{env, fs::read_to_string, path::PathBuf};
+use wasmi::Val;
+
+/// Session that keeps the compiler state and the memory between lines
+pub struct Repl {
+    compiler: Compiler,
+    /// Memory content that previous lines allocated
+    heap: Vec<u8>,
+    /// Values of the variables that previous lines defined
+    globals: Vec<(String, Val)>,
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl {
+            compiler: Compiler::new(),
+            heap: vec![],
+            globals: vec![],
+        }
+    }
+
+    /// Run only the new source code on top of the state that previous ones left
+    pub fn eval(&mut self, source: &str, path: &str) -> Result<Option<String>, String> {
+        let mut compiler = self.session();
+        // Static data of this line is placed after the heap of previous ones
+        compiler.allocator = compiler.allocator.max(self.heap.len() as i32);
+        let Some(mut ast) = compiler.parse(source) else {
+            return Err(report(&compiler, source, path));
+        };
+        // Variables of the top level are kept as globals for the following lines
+        let mut globals: Vec<String> = compiler.global.keys().cloned().collect();
+        for stmt in &ast.0 {
+            if let Stmt::Let(Scope::Local, Expr::Variable(name, _), _, _) = stmt {
+                globals.push(name.clone());
+            }
+        }
+        for stmt in &mut ast.0 {
+            globalize(stmt, &globals);
+        }
+        let Some(wat_code) = compiler.compile(&ast) else {
+            return Err(report(&compiler, source, path));
+        };
+        let binary = wat::parse_str(wat_code)
+            .map_err(|err| format!("failed to assemble generated code: {err}"))?;
+
+        let mut program = Program::new(&binary)?;
+        program.restore_heap(&self.heap)?;
+        for (name, value) in &self.globals {
+            program.set_global(&format!("global.{name}"), value.clone())?;
+        }
+        let result = program.start()?;
+        self.heap = program.heap();
+        self.globals = compiler
+            .global
+            .keys()
+            .filter_map(|name| Some((name.clone(), program.global(&format!("global.{name}"))?)))
+            .collect();
+        let result = result.map(|word| program.format(word, &compiler));
+        self.compiler = compiler;
+        Ok(result)
+    }
+
+    /// Type of the expression, without running it
+    pub fn type_of(&self, source: &str) -> Result<String, String> {
+        let mut compiler = self.session();
+        let ast = compiler.parse(source);
+        if ast.and_then(|ast| compiler.check(&ast)).is_none() {
+            return Err(report(&compiler, source, "<repl>"));
+        }
+        Ok(compiler.result.restore_alias(&compiler).format())
+    }
+
+    /// Compiler to process a new line, without what's left by the previous one
+    fn session(&self) -> Compiler {
+        let mut compiler = self.compiler.clone();
+        compiler.errors.clear();
+        compiler.poisoned.clear();
+        compiler.variable.clear();
+        compiler.result = Type::Void;
+        compiler
+    }
+
+    /// Handle the REPL command like `:type expr`
+    fn command(&mut self, line: &str) -> Result<Option<String>, String> {
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        match command {
+            ":type" => self.type_of(arg).map(Some),
+            ":summary" => {
+                summary::print_text(&self.compiler.summary())?;
+                Ok(None)
+            }
+            ":reset" => {
+                *self = Repl::new();
+                Ok(None)
+            }
+            ":load" => {
+                let source =
+                    read_to_string(arg).map_err(|err| format!("failed to read {arg}: {err}"))?;
+                self.eval(&source, arg)
+            }
+            _ => Err(format!(
+                "unknown command `{command}`, expected `:type expr`, `:summary`, `:reset` or `:load file`"
+            )),
+        }
+    }
+}
+
+/// Make assignments to the globals in nested blocks update them, not define locals
+fn globalize(stmt: &mut Stmt, globals: &[String]) {
+    let block = match stmt {
+        Stmt::Let(scope @ Scope::Local, Expr::Variable(name, _), _, _) => {
+            if globals.contains(name) {
+                *scope = Scope::Global;
+            }
+            return;
+        }
+        Stmt::If(_, then, other, _) => {
+            if let Some(other) = other {
+                globalize(other, globals);
+            }
+            then
+        }
+        Stmt::Match(_, arms, _) => {
+            for (_, body) in arms {
+                globalize_block(body, globals);
+            }
+            return;
+        }
+        Stmt::While(_, body, _) => body,
+        Stmt::Try(_, catch, _) => return globalize(catch, globals),
+        Stmt::Expr(expr, _) => expr,
+        _ => return,
+    };
+    globalize_block(block, globals);
+}
+
+fn globalize_block(expr: &mut Expr, globals: &[String]) {
+    if let Expr::Block(block, _) = expr {
+        for stmt in &mut block.0 {
+            globalize(stmt, globals);
+        }
+    }
+}
+
+/// Print the diagnostics of the compiler, and return the message to show
+fn report(compiler: &Compiler, source: &str, path: &str) -> String {
+    for err in &compiler.errors {
+        eprintln!("{}\n", err.render(source, path));
+    }
+    let count = compiler.errors.len();
+    format!("failed to compile due to {count} previous error(s)")
+}
+
+fn history_path() -> Option<PathBuf> {
+    Some(PathBuf::from(env::var_os("HOME")?).join(".lumo_history"))
+}
+
+pub fn start() -> Result<(), String> {
+    let mut editor = DefaultEditor::new().map_err(|err| err.to_string())?;
+    let history = history_path();
+    if let Some(path) = &history {
+        // History doesn't exist in the first session
+        let _ = editor.load_history(path);
+    }
+
+    println!("Lumo REPL");
+    let mut repl = Repl::new();
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.to_string()),
+        };
+        // A line is a statement, which doesn't need the separator `;`
+        let line = line.trim().trim_end_matches(';');
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+        let result = if line.starts_with(':') {
+            repl.command(line)
+        } else {
+            repl.eval(line, "<repl>")
+        };
+        match result {
+            Ok(Some(result)) => println!("{result}"),
+            Ok(None) => {}
+            Err(message) => eprintln!("Error: {message}"),
+        }
+    }
+    if let Some(path) = &history {
+        editor.save_history(path).map_err(|err| err.to_string())?;
+    }
+    println!("Bye");
+    Ok(())
+}
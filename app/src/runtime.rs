@@ -0,0 +1,404 @@
+use lumo_core::{BYTES, Compiler, PAGE_SIZE, Trap, Type};
+use std::io::Write;
+use wasmi::{
+    Caller, Engine, Error, ExternType, Instance, Linker, Memory, Module, Store, Val,
+    core::{Pages, TrapCode, ValType},
+};
+
+/// Value returned by a native function, converted into the declared result type
+enum Ret {
+    Int(i32),
+    Num(f32),
+    Void,
+}
+
+type Native = fn(&mut Caller<'_, ()>, &[Val]) -> Result<Ret, Error>;
+
+/// Run `_start` of the program with the native standard library,
+/// and return its result written in the source code syntax
+pub fn run(binary: &[u8], compiler: &Compiler) -> Result<Option<String>, String> {
+    let mut program = Program::new(binary)?;
+    let result = program.start()?;
+    Ok(result.map(|word| program.format(word, compiler)))
+}
+
+/// Instance of the compiled module with the native standard library
+pub struct Program {
+    store: Store<()>,
+    instance: Instance,
+}
+
+impl Program {
+    pub fn new(binary: &[u8]) -> Result<Program, String> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, binary).map_err(|err| err.to_string())?;
+        let mut store = Store::new(&engine, ());
+        let mut linker = Linker::<()>::new(&engine);
+        for import in module.imports() {
+            let (ExternType::Func(ty), "env") = (import.ty(), import.module()) else {
+                return Err(format!(
+                    "unknown import `{}.{}`",
+                    import.module(),
+                    import.name()
+                ));
+            };
+            let Some(native) = native(import.name()) else {
+                return Err(format!("unknown import `{}`", import.name()));
+            };
+            let result = ty.results().first().copied();
+            let func = move |mut caller: Caller<'_, ()>, args: &[Val], out: &mut [Val]| {
+                let value = native(&mut caller, args)?;
+                if let Some(slot) = out.first_mut() {
+                    *slot = match (value, result) {
+                        (Ret::Int(n), Some(ValType::F32)) => Val::F32((n as f32).into()),
+                        (Ret::Num(n), Some(ValType::I32)) => Val::I32(n as i32),
+                        (Ret::Num(n), _) => Val::F32(n.into()),
+                        (Ret::Int(n), _) => Val::I32(n),
+                        (Ret::Void, _) => Val::I32(0),
+                    };
+                }
+                Ok(())
+            };
+            linker
+                .func_new("env", import.name(), ty.clone(), func)
+                .map_err(|err| err.to_string())?;
+        }
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|err| err.to_string())?;
+        Ok(Program { store, instance })
+    }
+
+    /// Run `_start`, and return the raw bits of its result
+    pub fn start(&mut self) -> Result<Option<u64>, String> {
+        let start = self
+            .instance
+            .get_func(&self.store, "_start")
+            .ok_or("missing `_start` function")?;
+        let mut result = vec![Val::I32(0); start.ty(&self.store).results().len()];
+        if let Err(err) = start.call(&mut self.store, &[], &mut result) {
+            // Code that traps on purpose sets the cause before it
+            let trap = match (err.as_trap_code(), self.global("error")) {
+                (Some(TrapCode::UnreachableCodeReached), Some(Val::I32(code))) => {
+                    Trap::from_code(code)
+                }
+                _ => None,
+            };
+            return Err(match trap {
+                Some(trap) => format!("runtime error: {}", trap.message()),
+                None => format!("runtime error: {err}"),
+            });
+        }
+        Ok(match result.first() {
+            Some(Val::I32(n)) => Some(*n as u32 as u64),
+            Some(Val::F32(n)) => Some(f32::from(*n).to_bits() as u64),
+            Some(Val::I64(n)) => Some(*n as u64),
+            Some(Val::F64(n)) => Some(f64::from(*n).to_bits()),
+            _ => None,
+        })
+    }
+
+    /// Result of the program written in the source code syntax
+    pub fn format(&self, word: u64, compiler: &Compiler) -> String {
+        let mem = self.memory().map_or(&[][..], |mem| mem.data(&self.store));
+        read(mem, &compiler.result, word, compiler, true)
+    }
+
+    fn memory(&self) -> Option<Memory> {
+        self.instance.get_memory(&self.store, "mem")
+    }
+
+    /// Memory content that's allocated so far
+    pub fn heap(&self) -> Vec<u8> {
+        let end = self
+            .global("allocator")
+            .map_or(0, |val| val.i32().unwrap_or(0));
+        let mem = self.memory().map_or(&[][..], |mem| mem.data(&self.store));
+        mem.get(..end as usize).unwrap_or(mem).to_vec()
+    }
+
+    /// Restore the memory content that's allocated by another instance
+    pub fn restore_heap(&mut self, heap: &[u8]) -> Result<(), String> {
+        let memory = self.memory().ok_or("missing memory")?;
+        let size = memory.data(&self.store).len();
+        if heap.len() > size {
+            let pages = (heap.len() - size).div_ceil(PAGE_SIZE as usize);
+            let pages = Pages::new(pages as u32).ok_or("heap is too large to restore")?;
+            memory
+                .grow(&mut self.store, pages)
+                .map_err(|err| format!("failed to restore memory: {err}"))?;
+        }
+        // Static data of this module is placed after the heap
+        memory.data_mut(&mut self.store)[..heap.len()].copy_from_slice(heap);
+        Ok(())
+    }
+
+    /// Value of the exported global
+    pub fn global(&self, name: &str) -> Option<Val> {
+        let global = self.instance.get_global(&self.store, name)?;
+        Some(global.get(&self.store))
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Val) -> Result<(), String> {
+        let global = self.instance.get_global(&self.store, name);
+        let global = global.ok_or_else(|| format!("missing global `{name}`"))?;
+        global
+            .set(&mut self.store, value)
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Decode the value in the same layout as `ffi.mjs`,
+/// where the 32-bit types are in the lower half of the slot
+fn read(mem: &[u8], typ: &Type, word: u64, compiler: &Compiler, is_top: bool) -> String {
+    let ptr = word as u32 as i32;
+    let is_null = ptr == -1;
+    match typ {
+        Type::Integer => ptr.to_string(),
+        Type::Number => format_num(f32::from_bits(word as u32)),
+        Type::Long => (word as i64).to_string(),
+        Type::Double => f64::from_bits(word).to_string(),
+        Type::Unsigned => (word as u32).to_string(),
+        Type::Bool => (ptr != 0).to_string(),
+        Type::String if is_null => "null".to_string(),
+        Type::String if is_top => read_str(mem, ptr),
+        Type::String => format!("{:?}", read_str(mem, ptr)),
+        Type::Array(_) | Type::Dict(_) if is_null => "null".to_string(),
+        Type::Array(elm) => {
+            let items: Vec<_> = read_words(mem, ptr)
+                .into_iter()
+                .map(|word| read(mem, elm, word, compiler, false))
+                .collect();
+            format!("[{}]", items.join(", "))
+        }
+        Type::Dict(dict) => {
+            let fields: Vec<_> = dict
+                .iter()
+                .enumerate()
+                .map(|(n, (key, field))| {
+                    let word = load(mem, ptr as usize + n * BYTES as usize);
+                    format!("{key}: {}", read(mem, field, word, compiler, false))
+                })
+                .collect();
+            format!("@{{ {} }}", fields.join(", "))
+        }
+        Type::Enum(_) if typ.has_fields() && is_null => "null".to_string(),
+        // Object of the enum with fields has the tag, followed by the fields of the variant
+        Type::Enum(variants) if typ.has_fields() => {
+            let tag = load(mem, ptr as usize) as u32;
+            let Some((key, fields)) = variants.get_index(tag as usize) else {
+                return ptr.to_string();
+            };
+            let values: Vec<_> = fields
+                .iter()
+                .enumerate()
+                .map(|(n, field)| {
+                    let word = load(mem, ptr as usize + (n + 1) * BYTES as usize);
+                    read(mem, field, word, compiler, false)
+                })
+                .collect();
+            match values.as_slice() {
+                [] => format!("{}#{key}", typ.format()),
+                values => format!("{}#{key}({})", typ.format(), values.join(", ")),
+            }
+        }
+        Type::Enum(variants) => match variants.get_index(ptr as usize) {
+            Some((key, _)) => format!("{}#{key}", typ.format()),
+            None => ptr.to_string(),
+        },
+        Type::Function(..) if is_null => "null".to_string(),
+        // Code of the closure can't be shown, only its type
+        Type::Function(..) => format!("<{}>", typ.format()),
+        Type::Alias(name) => match compiler.alias.get(name) {
+            Some(typ) => read(mem, typ, word, compiler, is_top),
+            None => ptr.to_string(),
+        },
+        Type::Generic(..) => match typ.solve_alias(&mut compiler.clone(), vec![]) {
+            Some(typ) => read(mem, &typ, word, compiler, is_top),
+            None => ptr.to_string(),
+        },
+        Type::Nullable(typ) => read(mem, typ, word, compiler, is_top),
+        Type::Void | Type::Any | Type::Param(_) | Type::Var(_) => ptr.to_string(),
+    }
+}
+
+/// Number rounded in 6 decimal places
+fn format_num(n: f32) -> String {
+    let n = (n as f64 * 1e6).round() / 1e6;
+    n.to_string()
+}
+
+fn load(mem: &[u8], addr: usize) -> u64 {
+    let bytes = mem.get(addr..addr + BYTES as usize).unwrap_or(&[0; 8]);
+    u64::from_le_bytes(bytes.try_into().unwrap_or_default())
+}
+
+/// Null-terminated string in the memory
+fn read_str(mem: &[u8], ptr: i32) -> String {
+    let bytes = mem.get(ptr as usize..).unwrap_or_default();
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).to_string()
+}
+
+/// Elements of the array, whose length is stored ahead of them
+fn read_words(mem: &[u8], ptr: i32) -> Vec<u64> {
+    let addr = ptr as usize;
+    let len = load(mem, addr) as u32 as usize;
+    let elm = |n: usize| load(mem, addr + (n + 1) * BYTES as usize);
+    (0..len).map(elm).collect()
+}
+
+fn memory(caller: &Caller<'_, ()>) -> Result<Memory, Error> {
+    let memory = caller.get_export("mem").and_then(|mem| mem.into_memory());
+    memory.ok_or_else(|| Error::new("missing memory"))
+}
+
+/// Allocate the bytes by `malloc` of the program
+fn alloc(caller: &mut Caller<'_, ()>, bytes: &[u8]) -> Result<i32, Error> {
+    let malloc = caller.get_export("malloc").and_then(|f| f.into_func());
+    let malloc = malloc.ok_or_else(|| Error::new("missing `malloc` function"))?;
+    let ptr = malloc
+        .typed::<i32, i32>(&*caller)?
+        .call(&mut *caller, bytes.len() as i32)?;
+    memory(caller)?.write(&mut *caller, ptr as usize, bytes)?;
+    Ok(ptr)
+}
+
+fn str_arg(caller: &Caller<'_, ()>, arg: Option<&Val>) -> Result<String, Error> {
+    let mem = memory(caller)?;
+    Ok(read_str(mem.data(caller), int_arg(arg)))
+}
+
+fn words_arg(caller: &Caller<'_, ()>, arg: Option<&Val>) -> Result<Vec<u64>, Error> {
+    let mem = memory(caller)?;
+    Ok(read_words(mem.data(caller), int_arg(arg)))
+}
+
+fn int_arg(arg: Option<&Val>) -> i32 {
+    match arg {
+        Some(Val::I32(n)) => *n,
+        Some(Val::F32(n)) => f32::from(*n) as i32,
+        _ => 0,
+    }
+}
+
+/// Raw bits of the argument to be stored in the memory
+fn word_arg(arg: Option<&Val>) -> u64 {
+    match arg {
+        Some(Val::F32(n)) => f32::from(*n).to_bits() as u64,
+        Some(Val::I64(n)) => *n as u64,
+        Some(Val::F64(n)) => f64::from(*n).to_bits(),
+        arg => int_arg(arg) as u32 as u64,
+    }
+}
+
+fn write_str(caller: &mut Caller<'_, ()>, text: &str) -> Result<Ret, Error> {
+    let bytes = [text.as_bytes(), &[0]].concat();
+    Ok(Ret::Int(alloc(caller, &bytes)?))
+}
+
+fn write_words(caller: &mut Caller<'_, ()>, words: &[u64]) -> Result<Ret, Error> {
+    let len = words.len() as u64;
+    let bytes: Vec<u8> = [len]
+        .iter()
+        .chain(words)
+        .flat_map(|w| w.to_le_bytes())
+        .collect();
+    Ok(Ret::Int(alloc(caller, &bytes)?))
+}
+
+/// Native implementation of the standard library in `stdlib.mjs`
+fn native(name: &str) -> Option<Native> {
+    Some(match name {
+        "print" => |caller, args| {
+            println!("{}", str_arg(caller, args.first())?);
+            Ok(Ret::Void)
+        },
+        "write" => |caller, args| {
+            print!("{}", str_arg(caller, args.first())?);
+            std::io::stdout()
+                .flush()
+                .map_err(|err| Error::new(err.to_string()))?;
+            Ok(Ret::Void)
+        },
+        "to_str" => |caller, args| {
+            let text = match args.first() {
+                Some(Val::F32(n)) => f32::from(*n).to_string(),
+                arg => int_arg(arg).to_string(),
+            };
+            write_str(caller, &text)
+        },
+        "to_num" => |caller, args| {
+            let text = str_arg(caller, args.first())?;
+            Ok(Ret::Num(text.trim().parse().unwrap_or(f32::NAN)))
+        },
+        "repeat" => |caller, args| {
+            let text = str_arg(caller, args.first())?;
+            let count = int_arg(args.get(1)).max(0) as usize;
+            write_str(caller, &text.repeat(count))
+        },
+        "concat" => |caller, args| {
+            let lhs = str_arg(caller, args.first())?;
+            let rhs = str_arg(caller, args.get(1))?;
+            write_str(caller, &(lhs + &rhs))
+        },
+        "strcmp" => |caller, args| {
+            let lhs = str_arg(caller, args.first())?;
+            let rhs = str_arg(caller, args.get(1))?;
+            Ok(Ret::Int((lhs == rhs) as i32))
+        },
+        "strlen" => |caller, args| {
+            let text = str_arg(caller, args.first())?;
+            Ok(Ret::Int(text.chars().count() as i32))
+        },
+        "split" => |caller, args| {
+            let text = str_arg(caller, args.first())?;
+            let delimiter = str_arg(caller, args.get(1))?;
+            let mut words = vec![];
+            for part in text.split(delimiter.as_str()) {
+                let Ret::Int(ptr) = write_str(caller, part)? else {
+                    continue;
+                };
+                words.push(ptr as u32 as u64);
+            }
+            write_words(caller, &words)
+        },
+        "array" => |caller, args| {
+            let len = int_arg(args.get(1)).max(0) as usize;
+            write_words(caller, &vec![word_arg(args.first()); len])
+        },
+        "slice" => |caller, args| {
+            let words = words_arg(caller, args.first())?;
+            let index = |arg| {
+                let n = int_arg(arg);
+                let n = if n < 0 { words.len() as i32 + n } else { n };
+                n.clamp(0, words.len() as i32) as usize
+            };
+            let (start, end) = (index(args.get(1)), index(args.get(2)));
+            write_words(caller, words.get(start..end).unwrap_or_default())
+        },
+        "arrlen" => |caller, args| {
+            let mem = memory(caller)?;
+            Ok(Ret::Int(
+                load(mem.data(&*caller), int_arg(args.first()) as usize) as u32 as i32,
+            ))
+        },
+        "join" => |caller, args| {
+            let mem = memory(caller)?;
+            let words = words_arg(caller, args.first())?;
+            let delimiter = str_arg(caller, args.get(1))?;
+            let parts: Vec<_> = words
+                .iter()
+                .map(|&word| read_str(mem.data(&*caller), word as u32 as i32))
+                .collect();
+            write_str(caller, &parts.join(&delimiter))
+        },
+        "append" => |caller, args| {
+            let lhs = words_arg(caller, args.first())?;
+            let rhs = words_arg(caller, args.get(1))?;
+            write_words(caller, &[lhs, rhs].concat())
+        },
+        _ => return None,
+    })
+}
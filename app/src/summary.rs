@@ -0,0 +1,59 @@
+use lumo_core::Summary;
+use std::io::{self, Write};
+
+pub fn print_text(summary: &Summary) -> Result<(), String> {
+    write_text(&mut io::stdout().lock(), summary).map_err(|err| err.to_string())
+}
+
+fn write_text(out: &mut impl Write, summary: &Summary) -> io::Result<()> {
+    writeln!(out, "# Type Inference Summary")?;
+    writeln!(out, "Functions:")?;
+    let mut functions = summary.exports.clone();
+    functions.extend(summary.functions.clone());
+    for (name, func) in &functions {
+        let arguments: Vec<_> = func
+            .arguments
+            .iter()
+            .map(|(name, typ)| format!("{name}: {typ}"))
+            .collect();
+        writeln!(out, " - {name}({}): {}", arguments.join(", "), func.returns)?;
+    }
+    writeln!(out, "Instances:")?;
+    for (name, func) in &summary.instances {
+        let arguments: Vec<_> = func
+            .arguments
+            .iter()
+            .map(|(name, typ)| format!("{name}: {typ}"))
+            .collect();
+        writeln!(out, " - {name}({}): {}", arguments.join(", "), func.returns)?;
+    }
+    writeln!(out, "Overloads:")?;
+    for (function, overload) in &summary.overloads {
+        match &overload.rhs {
+            Some(rhs) => writeln!(out, " - {function}: {} {} {rhs}", overload.lhs, overload.op)?,
+            None => writeln!(out, " - {function}: {}{}", overload.op, overload.lhs)?,
+        }
+    }
+    writeln!(out, "Variables:")?;
+    for (name, typ) in &summary.variables {
+        writeln!(out, " - {name}: {typ}")?;
+    }
+    writeln!(out, "Globals:")?;
+    for (name, typ) in &summary.globals {
+        writeln!(out, " - {name}: {typ}")?;
+    }
+    writeln!(out, "Aliases:")?;
+    for (name, typ) in &summary.aliases {
+        writeln!(out, " - {name}: {typ}")?;
+    }
+    writeln!(out, "Module:")?;
+    for (name, args) in &summary.macros {
+        writeln!(out, " - {name}({})", args.join(", "))?;
+    }
+    writeln!(out, "Returns: {}", summary.result)
+}
+
+pub fn print_json(summary: &Summary) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(summary).map_err(|err| err.to_string())?;
+    writeln!(io::stdout().lock(), "{json}").map_err(|err| err.to_string())
+}
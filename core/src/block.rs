@@ -4,24 +4,51 @@ use crate::*;
 pub struct Block(pub Vec<Stmt>);
 
 impl Node for Block {
-    fn parse(source: &str) -> Option<Block> {
-        Some(Block(
-            tokenize(source, &[";"], false, false, false)?
-                .iter()
-                .map(String::as_str)
-                .map(Stmt::parse)
-                .collect::<Option<Vec<_>>>()?,
-        ))
+    fn parse(p: &mut Parser) -> Option<Block> {
+        let (mut block, mut is_failed) = (vec![], false);
+        loop {
+            // Skip the broken statement to report errors of the rest as well
+            let start = p.index;
+            match Stmt::parse(p) {
+                Some(stmt) if p.is_terminated() => block.push(stmt),
+                stmt => {
+                    if stmt.is_some() {
+                        p.expected::<()>("`;`");
+                    }
+                    p.recover(start);
+                    is_failed = true;
+                }
+            }
+            let Some(mut span) = p.eat(Token::Punct(';')) else {
+                break;
+            };
+            while p.is_terminated() {
+                let note = "statements are separated by `;`, so it can't be put after the last one";
+                let err = Diagnostic::error("empty statement", span).note(note);
+                p.errors.push(err);
+                is_failed = true;
+                span = p.eat(Token::Punct(';'))?;
+            }
+        }
+        if is_failed { None } else { Some(Block(block)) }
     }
 
     fn compile(&self, ctx: &mut Compiler) -> Option<String> {
         let (mut result, Block(block)) = (vec![], self);
         for (n, line) in block.iter().enumerate() {
+            // Statement is inferred with the variables as they're before it, like in the check
+            let (var_ctx, narrowed) = (ctx.variable.clone(), ctx.narrowed.clone());
+            let typ = line.infer(ctx)?;
+            ctx.variable = var_ctx;
+            ctx.narrowed = narrowed;
             let mut output = line.compile(ctx)?;
             if n != block.len() - 1 {
-                if !matches!(line.infer(ctx)?, Type::Void) {
+                if !matches!(typ, Type::Void) {
                     output.push_str("(drop)");
                 }
+                output = ctx.statement(output);
+            } else {
+                output = ctx.passing(&typ, output)?;
             }
             result.push(output);
         }
@@ -33,15 +60,20 @@ impl Node for Block {
         let fun_ctx = ctx.function.clone();
         let mcr_ctx = ctx.module.clone();
 
+        // Keep checking the rest of statements to report independent errors
         let Block(block) = self.clone();
-        let mut result = Type::Void;
+        let (mut result, mut is_failed) = (Type::Void, false);
         for line in block {
-            result = line.infer(ctx)?;
+            if let Some(typ) = line.infer(ctx) {
+                result = typ;
+            } else {
+                is_failed = true;
+            }
         }
 
         ctx.variable = var_ctx;
         ctx.function = fun_ctx;
         ctx.module = mcr_ctx;
-        Some(result)
+        if is_failed { None } else { Some(result) }
     }
 }
@@ -0,0 +1,153 @@
+use crate::*;
+
+/// Runtime check that the module does in addition to what WebAssembly does
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Check {
+    /// Array indexes are in `-len..len`, instead of wrapped by the length
+    Bounds,
+    /// Objects aren't null when their fields or elements are accessed
+    Null,
+}
+
+/// Cause of the runtime error, that's set to the exported `error` global before trapping
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trap {
+    OutOfMemory = 1,
+    OutOfBounds = 2,
+    NullPointer = 3,
+    StackOverflow = 4,
+}
+
+impl Trap {
+    const ALL: [Trap; 4] = [
+        Trap::OutOfMemory,
+        Trap::OutOfBounds,
+        Trap::NullPointer,
+        Trap::StackOverflow,
+    ];
+
+    pub fn from_code(code: i32) -> Option<Trap> {
+        Trap::ALL.into_iter().find(|trap| *trap as i32 == code)
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Trap::OutOfMemory => "out of memory, reached the maximum page count",
+            Trap::OutOfBounds => "index out of bounds of the array",
+            Trap::NullPointer => "access to the field or element of null",
+            Trap::StackOverflow => "shadow stack overflow, too many objects are in use at once",
+        }
+    }
+
+    /// Function that traps, named to be shown in the stack trace
+    fn function(&self) -> &'static str {
+        match self {
+            Trap::OutOfMemory => "out_of_memory",
+            Trap::OutOfBounds => "out_of_bounds",
+            Trap::NullPointer => "null_pointer",
+            Trap::StackOverflow => "stack_overflow",
+        }
+    }
+}
+
+impl Compiler {
+    /// Address of the array element, whose index is checked or wrapped by the length
+    pub(crate) fn element(&mut self, array: &Expr, index: &Expr) -> Option<String> {
+        if self.checks.contains(&Check::Bounds) || self.checks.contains(&Check::Null) {
+            let [array, index] = [array.compile(self)?, index.compile(self)?];
+            return Some(format!("(call $element {array} {index})"));
+        }
+        let (array, index) = (Box::new(array.clone()), Box::new(index.clone()));
+        address_calc!(array, index).compile(self)
+    }
+
+    /// Address of the field at the offset in the dict object
+    pub(crate) fn field(&mut self, object: &Expr, offset: i32) -> Option<String> {
+        if self.checks.contains(&Check::Null) {
+            let object = object.compile(self)?;
+            return Some(format!(
+                "(i32.add (call $non_null {object}) (i32.const {offset}))"
+            ));
+        }
+        let object = Box::new(object.clone());
+        offset_calc!(object, offset).compile(self)
+    }
+
+    /// Index that's out of range is an error, if both the array and the index are literals
+    pub(crate) fn check_index(&mut self, array: &Expr, index: &Expr) -> Option<()> {
+        let (Expr::Literal(Value::Array(array), _), Expr::Literal(Value::Integer(index), _)) =
+            (array, index)
+        else {
+            return Some(());
+        };
+        let length = array.len() as i32;
+        if self.checks.contains(&Check::Bounds) && !(-length..length).contains(index) {
+            self.report(format!(
+                "index {index} is out of bounds of the array whose length is {length}"
+            ));
+            return None;
+        }
+        Some(())
+    }
+
+    /// Global of the error code and the functions that trap, with the checks enabled
+    pub(crate) fn expand_checks(&self) -> String {
+        let mut result =
+            vec!["(global $error (export \"error\") (mut i32) (i32.const 0))".to_string()];
+        for trap in Trap::ALL {
+            result.push(format!(
+                "(func ${} (global.set $error (i32.const {})) unreachable)",
+                trap.function(),
+                trap as i32
+            ));
+        }
+        if self.checks.contains(&Check::Null) {
+            result.push(
+                concat!(
+                    "(func $non_null (param $ptr i32) (result i32) ",
+                    "(if (i32.eq (local.get $ptr) (i32.const -1)) (then (call $null_pointer))) (local.get $ptr))"
+                )
+                .to_string(),
+            );
+        }
+        if self.checks.contains(&Check::Bounds) {
+            // Negative index counts from the end
+            result.push(
+                concat!(
+                    "(func $bounds (param $index i32) (param $length i32) (result i32) ",
+                    "(if (i32.lt_s (local.get $index) (i32.const 0)) (then ",
+                    "(local.set $index (i32.add (local.get $index) (local.get $length))))) ",
+                    "(if (i32.ge_u (local.get $index) (local.get $length)) (then (call $out_of_bounds))) ",
+                    "(local.get $index))"
+                )
+                .to_string(),
+            );
+        }
+        if self.checks.contains(&Check::Bounds) || self.checks.contains(&Check::Null) {
+            let array = if self.checks.contains(&Check::Null) {
+                "(call $non_null (local.get $array))"
+            } else {
+                "(local.get $array)"
+            };
+            let index = if self.checks.contains(&Check::Bounds) {
+                "(call $bounds (local.get $index) (local.get $length))"
+            } else {
+                concat!(
+                    "(i32.rem_s (i32.add (i32.rem_s (local.get $index) (local.get $length)) ",
+                    "(local.get $length)) (local.get $length))"
+                )
+            };
+            result.push(format!(
+                concat!(
+                    "(func $element (param $array i32) (param $index i32) (result i32) (local $length i32) ",
+                    "(local.set $length (i32.load {array})) (local.set $index {index}) ",
+                    "(i32.add (i32.add (local.get $array) (i32.const {bytes})) (i32.mul (local.get $index) (i32.const {bytes}))))"
+                ),
+                array = array,
+                index = index,
+                bytes = BYTES,
+            ));
+        }
+        join!(result)
+    }
+}
@@ -0,0 +1,240 @@
+use crate::*;
+
+impl Compiler {
+    /// Variable of the enclosing function, that the lambda captures as its own local
+    pub(crate) fn capture(&mut self, name: &str) -> Option<Type> {
+        let typ = self.outer.get(name)?.clone();
+        self.captured.insert(name.to_string(), typ.clone());
+        self.variable.insert(name.to_string(), typ.clone());
+        Some(typ)
+    }
+
+    /// Type of the named function, when it's referred as a value
+    pub(crate) fn function_type(&self, name: &str) -> Option<Type> {
+        let function = self.function.get(name).or(self.export.get(name))?;
+        let args = function.arguments.values().cloned().collect();
+        Some(Type::Function(args, Box::new(function.returns.clone())))
+    }
+
+    /// Type of the closure in the variable `name`, if it's called instead of a function
+    pub(crate) fn callee(&mut self, name: &str) -> Option<Type> {
+        let scopes = [&self.global, &self.variable, &self.argument, &self.outer];
+        let typ = scopes
+            .into_iter()
+            .find_map(|scope| scope.get(name))?
+            .clone();
+        if !matches!(typ.infer(self)?.non_null(), Type::Function(..)) {
+            return None;
+        }
+        Expr::Variable(name.to_string(), self.span).infer(self)
+    }
+
+    /// Return type of the closure call, checking the arguments given
+    pub(crate) fn indirect_type(&mut self, name: &str, typ: Type, args: &[Expr]) -> Option<Type> {
+        let typ = typ.infer(self)?;
+        let typ = self.deref(typ, "call")?;
+        let Type::Function(params, ret) = typ else {
+            return None;
+        };
+        arglen_check!(name, args, params, "closure", self);
+        for (arg, param) in args.iter().zip(&params) {
+            self.span = arg.span();
+            self.expect_value(param, arg)?;
+        }
+        Some(*ret)
+    }
+
+    /// Call of the function at the table index in the closure, passing the closure itself
+    /// as the environment
+    pub(crate) fn call_indirect(&mut self, name: &str, args: &[Expr]) -> Option<String> {
+        let typ = self.callee(name)?.infer(self)?.non_null();
+        let Type::Function(params, ret) = typ else {
+            return None;
+        };
+        let mut signature = vec!["(param i32)".to_string()];
+        for param in &params {
+            signature.push(format!("(param {})", param.compile(self)?));
+        }
+        signature.push(compile_return!(ret, self));
+        let closure = Expr::Variable(name.to_string(), self.span).compile(self)?;
+        let mut values = vec![];
+        for arg in args {
+            values.push(arg.compile(self)?);
+        }
+        let table = if self.checks.contains(&Check::Null) {
+            format!("(call $non_null {closure})")
+        } else {
+            closure.clone()
+        };
+        Some(format!(
+            "(call_indirect {} {closure} {} (i32.load {table}))",
+            join!(signature),
+            join!(values)
+        ))
+    }
+
+    /// Type of the lambda, checking its body with the arguments
+    pub(crate) fn lambda_type(&mut self, params: &[Expr], body: &Expr) -> Option<Type> {
+        let (typ, _) = self.enter_lambda(|ctx| {
+            check_args!(params, ctx);
+            let ret = body.infer(ctx)?;
+            let args = ctx.argument.values().cloned().collect();
+            Some(Type::Function(args, Box::new(ret)))
+        })?;
+        Some(typ)
+    }
+
+    /// Function of the lambda body, and the closure that has the variables it captured
+    pub(crate) fn lambda(&mut self, params: &[Expr], body: &Expr) -> Option<String> {
+        let typ = self.lambda_type(params, body)?;
+        let Type::Function(_, ret) = &typ else {
+            return None;
+        };
+        let name = format!("lambda.{}", self.count);
+        self.count += 1;
+        let (code, captured) = self.enter_lambda(|ctx| {
+            check_args!(params, ctx);
+            let body = body.compile(ctx)?;
+            // Captured values are copied from the environment at the start
+            let mut load = vec![];
+            for (index, (var, typ)) in ctx.captured.clone().iter().enumerate() {
+                let value = format!(
+                    "({}.load offset={} (local.get $env))",
+                    typ.compile(ctx)?,
+                    (index as i32 + 1) * BYTES
+                );
+                load.push(ctx.assign(Scope::Local, var, typ, value)?);
+            }
+            let mut params = vec!["(param $env i32)".to_string()];
+            for (arg, typ) in ctx.argument.clone() {
+                params.push(format!("(param ${arg} {})", typ.compile(ctx)?));
+            }
+            Some(format!(
+                "(func ${name} {params} {ret} {locals} {temp} {prologue} {load} {body})",
+                params = join!(params),
+                ret = compile_return!(ret, ctx),
+                locals = expand_local!(ctx),
+                temp = ctx.epilogue_local(ret)?,
+                prologue = ctx.prologue()?,
+                load = join!(load),
+                body = ctx.epilogue(ret, body)?,
+            ))
+        })?;
+        self.declare.insert(name.clone(), code);
+        self.closure(name, typ, captured)
+    }
+
+    /// Closure of the named function, through a wrapper that ignores the environment
+    pub(crate) fn function_ref(&mut self, name: &str) -> Option<String> {
+        let typ = self.function_type(name)?;
+        let Type::Function(params, ret) = &typ else {
+            return None;
+        };
+        let wrapper = format!("ref.{name}");
+        if !self.declare.contains_key(&wrapper) {
+            let mut signature = vec!["(param $env i32)".to_string()];
+            let mut args = vec![];
+            for (index, param) in params.iter().enumerate() {
+                signature.push(format!("(param $arg.{index} {})", param.compile(self)?));
+                args.push(format!("(local.get $arg.{index})"));
+            }
+            signature.push(compile_return!(ret, self));
+            let code = format!(
+                "(func ${wrapper} {} (call ${name} {}))",
+                join!(signature),
+                join!(args)
+            );
+            self.declare.insert(wrapper.clone(), code);
+        }
+        self.closure(wrapper, typ, IndexMap::new())
+    }
+
+    /// Object of the table index followed by the captured values,
+    /// that's in the static data if nothing is captured
+    fn closure(&mut self, name: String, typ: Type, captured: Dict) -> Option<String> {
+        let (index, _) = self
+            .table
+            .insert_full(name, (typ.clone(), captured.clone()));
+        if captured.is_empty() {
+            let addr = self.allocator;
+            let bytes: String = (index as i32)
+                .to_le_bytes()
+                .iter()
+                .map(|byte| format!("\\{byte:02x}"))
+                .collect();
+            self.data
+                .push(format!(r#"(data (i32.const {addr}) "{bytes}")"#));
+            self.allocator += BYTES;
+            return Some(format!("(i32.const {addr})"));
+        }
+        let mut values = vec![];
+        let mut params = vec![];
+        let mut stores = vec![];
+        for (n, (var, field)) in captured.iter().enumerate() {
+            if self.is_object(field)? {
+                let field = field.restore_alias(self).format();
+                self.report(format!(
+                    "can't capture {field} with the Wasm GC, as closures are in the linear memory"
+                ));
+                return None;
+            }
+            values.push(Expr::Variable(var.clone(), self.span).compile(self)?);
+            let field = field.compile(self)?;
+            params.push(format!("(param $field.{n} {field})"));
+            stores.push(format!(
+                "({field}.store offset={} (local.get $ptr) (local.get $field.{n}))",
+                (n as i32 + 1) * BYTES
+            ));
+        }
+        let code = format!(
+            concat!(
+                "(func $closure.{index} {params} (result i32) (local $ptr i32) ",
+                "(local.set $ptr (call $malloc (i32.const {size}))) ",
+                "(i32.store (local.get $ptr) (i32.const {index})) {stores} (local.get $ptr))"
+            ),
+            index = index,
+            params = join!(params),
+            size = (captured.len() as i32 + 1) * BYTES,
+            stores = join!(stores),
+        );
+        self.declare.insert(format!("closure.{index}"), code);
+        self.adopt(&typ, format!("(call $closure.{index} {})", join!(values)))
+    }
+
+    /// Run the check or compilation of the lambda body in its own scope, where the variables
+    /// of the enclosing functions are captured when they're used, and return the captured ones
+    fn enter_lambda<T>(&mut self, run: impl FnOnce(&mut Self) -> Option<T>) -> Option<(T, Dict)> {
+        let mut outer = self.outer.clone();
+        outer.extend(self.argument.clone());
+        outer.extend(self.variable.clone());
+        let outer = std::mem::replace(&mut self.outer, outer);
+        let variable = std::mem::take(&mut self.variable);
+        let argument = std::mem::take(&mut self.argument);
+        let captured = std::mem::take(&mut self.captured);
+        let narrowed = std::mem::take(&mut self.narrowed);
+        let in_while = std::mem::replace(&mut self.in_while, false);
+        let result = run(self);
+        let inner = std::mem::replace(&mut self.captured, captured);
+        self.narrowed = narrowed;
+        self.outer = outer;
+        self.variable = variable;
+        self.argument = argument;
+        self.in_while = in_while;
+        Some((result?, inner))
+    }
+
+    /// Generated functions, and the table of the ones that closures call indirectly
+    /// at the index in the closure
+    pub(crate) fn expand_declare(&self) -> Vec<String> {
+        let mut result: Vec<_> = self.declare.values().cloned().collect();
+        if !self.table.is_empty() {
+            let names: Vec<_> = self.table.keys().map(|name| format!("${name}")).collect();
+            result.push(format!(
+                "(table $closures (export \"closures\") {} funcref) (elem (i32.const 0) {})",
+                names.len(),
+                join!(names)
+            ));
+        }
+        result
+    }
+}
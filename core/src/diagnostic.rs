@@ -0,0 +1,120 @@
+use crate::*;
+
+/// Byte range in the source code
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Structured message that reports a problem in the source code
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Where the problem is
+    pub primary: Span,
+    /// Related locations with a short label each
+    pub secondary: Vec<(Span, String)>,
+    pub notes: Vec<String>,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Smallest span that covers both spans
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    /// Line and column numbers (1-based) of the start position
+    pub fn location(&self, source: &str) -> (usize, usize) {
+        let start = self.start.min(source.len());
+        let before = source.get(..start).unwrap_or_default();
+        let line = before.matches('\n').count() + 1;
+        let line_head = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let column = before.get(line_head..).unwrap_or_default().chars().count() + 1;
+        (line, column)
+    }
+}
+
+impl Diagnostic {
+    pub fn error(message: impl ToString, primary: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.to_string(),
+            primary,
+            secondary: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn label(mut self, span: Span, label: impl ToString) -> Self {
+        self.secondary.push((span, label.to_string()));
+        self
+    }
+
+    pub fn note(mut self, note: impl ToString) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    /// Human readable report with line/column and a caret snippet
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let (line, column) = self.primary.location(source);
+        let lines = self
+            .secondary
+            .iter()
+            .map(|(span, _)| span.location(source).0);
+        let width = lines.fold(line, usize::max).to_string().len();
+        let gutter = SPACE[0].repeat(width);
+
+        let mut result = vec![
+            format!("{severity}: {}", self.message),
+            format!("{gutter}--> {path}:{line}:{column}"),
+            format!("{gutter} |"),
+        ];
+        result.extend(snippet(source, self.primary, '^', "", width));
+        for (span, label) in &self.secondary {
+            result.extend(snippet(source, *span, '-', label, width));
+        }
+        for note in &self.notes {
+            result.push(format!("{gutter} = note: {note}"));
+        }
+        result.join("\n")
+    }
+}
+
+/// Source line of the span with the span underlined by the marker
+fn snippet(source: &str, span: Span, marker: char, label: &str, width: usize) -> Vec<String> {
+    let (line, column) = span.location(source);
+    let text = source.lines().nth(line - 1).unwrap_or_default();
+    let text_len = text.chars().count();
+    let span_len = source
+        .get(span.start.min(source.len())..span.end.min(source.len()))
+        .map(|i| i.lines().next().unwrap_or_default().chars().count())
+        .unwrap_or_default();
+    let underline = marker
+        .to_string()
+        .repeat(span_len.min(text_len.saturating_sub(column - 1)).max(1));
+    let indent = SPACE[0].repeat(column - 1);
+    let gutter = SPACE[0].repeat(width);
+    vec![
+        format!("{line:>width$} | {text}"),
+        format!("{gutter} | {indent}{underline} {label}")
+            .trim_end()
+            .to_string(),
+    ]
+}
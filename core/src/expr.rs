@@ -1,142 +1,63 @@
-use crate::*;
+use crate::{null::field_path, object::is_generic, variant::enum_size, *};
 
 #[derive(Debug, Clone)]
 pub enum Expr {
-    Literal(Value),
-    Variable(String),
-    Operator(Box<Op>),
-    Call(String, Vec<Expr>),
-    Macro(String, Vec<Type>),
-    Index(Box<Expr>, Box<Expr>),
-    Field(Box<Expr>, String),
-    Block(Block),
-    Clone(Box<Expr>),
-    Peek(Box<Expr>, Type),
-    Poke(Box<Expr>, Box<Expr>),
+    Literal(Value, Span),
+    Variable(String, Span),
+    Operator(Box<Op>, Span),
+    Call(String, Vec<Expr>, Span),
+    Macro(String, Vec<Type>, Span),
+    Index(Box<Expr>, Box<Expr>, Span),
+    Field(Box<Expr>, String, Span),
+    Block(Block, Span),
+    Clone(Box<Expr>, Span),
+    Free(Box<Expr>, Span),
+    Peek(Box<Expr>, Type, Span),
+    Poke(Box<Expr>, Box<Expr>, Span),
+    /// Function with the arguments and the body, that captures variables it uses
+    Lambda(Vec<Expr>, Box<Expr>, Span),
 }
 
 impl Node for Expr {
-    fn parse(source: &str) -> Option<Expr> {
-        let mut token = source.trim().to_owned();
-        // Operator
-        if let Some(literal) = Op::parse(&token) {
-            Some(Expr::Operator(Box::new(literal)))
-        // Literal value
-        } else if let Some(literal) = Value::parse(&token) {
-            Some(Expr::Literal(literal))
-        // Formatted string (f-string)
-        } else if token.starts_with("f\"") && token.ends_with('"') {
-            let str = str_format(token.get(2..token.len() - 1)?)?;
-            let mut result: Option<Expr> = None;
-            macro_rules! concat {
-                ($expr: expr) => {
-                    Some(if let Some(result) = result {
-                        Expr::Operator(Box::new(Op::Add(result, $expr.clone())))
-                    } else {
-                        $expr
-                    })
-                };
-            }
-            for elm in str {
-                result = if elm.starts_with("{") && elm.ends_with("}") {
-                    let elm = elm.get(1..elm.len() - 1)?.trim();
-                    concat!(Expr::Operator(Box::new(Op::Cast(
-                        Expr::Block(Block::parse(elm)?),
-                        Type::String,
-                    ))))
-                } else {
-                    concat!(Expr::Literal(Value::String(elm)))
-                }
-            }
-            result.clone()
-        // Prioritize expression `(expr)`
-        } else if token.starts_with("(") && token.ends_with(")") {
-            let token = token.get(1..token.len() - 1)?.trim();
-            Some(Expr::parse(token)?)
-        // Code block `{ stmt; ... }`
-        } else if token.starts_with("{") && token.ends_with("}") {
-            let token = token.get(1..token.len() - 1)?.trim();
-            Some(Expr::Block(Block::parse(token)?))
-        // Index access `array[index]`
-        } else if token.contains("[") && token.ends_with("]") {
-            let token = tokenize(&token, &["["], false, true, true)?;
-            let array = Expr::parse(&token.get(..token.len() - 1)?.concat())?;
-            let index = token.last()?.get(1..token.last()?.len() - 1)?;
-            Some(Expr::Index(Box::new(array), Box::new(Expr::parse(index)?)))
-        // Function call `name(args, ...)`
-        } else if token.contains("(") && token.ends_with(")") {
-            let token = tokenize(&token, &["("], false, true, true)?;
-            let args = token.last()?.get(1..token.last()?.len() - 1)?;
-            let args = tokenize(args, &[","], false, true, false)?;
-            let name = token.get(..token.len() - 1)?.concat();
-            if let Some(name) = name.strip_suffix("!") {
-                let args = args
-                    .iter()
-                    .map(|i| Type::parse(&i))
-                    .collect::<Option<Vec<_>>>()?;
-                let Expr::Variable(name) = Expr::parse(&name)? else {
-                    return None;
-                };
-                Some(Expr::Macro(name, args))
-            } else {
-                let args = args
-                    .iter()
-                    .map(|i| Expr::parse(&i))
-                    .collect::<Option<Vec<_>>>()?;
-                let (name, args) = match Expr::parse(&name)? {
-                    Expr::Variable(name) => (name, args),
-                    Expr::Field(obj, name) => (name, [vec![*obj], args].concat()),
-                    _ => return None,
-                };
-                if name == "memcpy" {
-                    let [obj] = args.as_slice() else { return None };
-                    return Some(Expr::Clone(Box::new(obj.clone())));
-                }
-                Some(Expr::Call(name, args))
-            }
-        // Dictionary access `dict.field`
-        } else if token.contains(".") {
-            let (dict, field) = token.rsplit_once(".")?;
-            let mut field = field.trim().to_owned();
-            if !is_identifier(&mut field) {
-                return None;
-            };
-            Some(Expr::Field(Box::new(Expr::parse(dict)?), field.to_owned()))
-        // Enumerate access `( a | b )#a`
-        } else if source.contains("#") {
-            let (typ, key) = source.rsplit_once("#")?;
-            let mut key = key.trim().to_owned();
-            if !is_identifier(&mut key) {
-                return None;
-            };
-            let key = Value::Enum(Type::parse(typ)?, key.to_owned());
-            Some(Expr::Literal(key))
-        // Variable reference
-        } else if is_identifier(&mut token) {
-            Some(Expr::Variable(token.to_string()))
-        } else {
-            None
-        }
+    fn parse(p: &mut Parser) -> Option<Expr> {
+        Expr::parse_binary(p, 0)
     }
 
     fn compile(&self, ctx: &mut Compiler) -> Option<String> {
-        Some(match self {
-            Expr::Operator(oper) => oper.compile(ctx)?,
-            Expr::Variable(name) => {
-                let is_global = ctx.global.contains_key(name);
-                let scope = if is_global { "global" } else { "local" };
-                format!("({scope}.get ${name})",)
+        let outer = std::mem::replace(&mut ctx.span, self.span());
+        let code = match self {
+            Expr::Operator(oper, _) => oper.compile(ctx)?,
+            Expr::Variable(name, _) if ctx.global.contains_key(name) => {
+                format!("(global.get ${name})")
+            }
+            // Function that isn't shadowed by a variable is referred as a closure
+            Expr::Variable(name, _)
+                if !ctx.variable.contains_key(name)
+                    && !ctx.argument.contains_key(name)
+                    && ctx.capture(name).is_none()
+                    && ctx.function_type(name).is_some() =>
+            {
+                ctx.function_ref(name)?
             }
-            Expr::Literal(literal) => literal.compile(ctx)?,
-            Expr::Call(name, args) => format!(
-                "(call ${name} {})",
+            Expr::Variable(name, _) => format!("(local.get ${name})"),
+            Expr::Literal(literal, _) => literal.compile(ctx)?,
+            Expr::Call(name, args, _) if ctx.callee(name).is_some() => {
+                ctx.call_indirect(name, args)?
+            }
+            Expr::Lambda(params, body, _) => ctx.lambda(params, body)?,
+            Expr::Call(name, args, _) => format!(
+                "(call ${} {})",
+                match ctx.template.contains_key(name) {
+                    true => ctx.instance(name, args)?,
+                    false => name.clone(),
+                },
                 join!(
                     args.iter()
                         .map(|x| x.compile(ctx))
                         .collect::<Option<Vec<_>>>()?
                 )
             ),
-            Expr::Macro(name, args) => {
+            Expr::Macro(name, args, _) => {
                 if let Some((params, expr)) = ctx.module.get(name).cloned() {
                     let mut old_types = IndexMap::new();
                     for (param, arg) in params.iter().zip(args) {
@@ -148,7 +69,7 @@ impl Node for Expr {
                     }
                     let mut body = expr.compile(ctx)?;
                     for (param, arg) in params.iter().zip(args) {
-                        let var = Expr::Variable(param.to_owned()).compile(ctx)?;
+                        let var = Expr::Variable(param.to_owned(), self.span()).compile(ctx)?;
                         body = body.replace(&var, &arg.compile(ctx)?);
                     }
                     ctx.alias.extend(old_types);
@@ -157,72 +78,122 @@ impl Node for Expr {
                     return None;
                 }
             }
-            Expr::Index(array, index) => {
-                let typ = array.infer(ctx)?;
+            Expr::Index(array, index, _) => {
+                let typ = array.infer(ctx)?.infer(ctx)?;
+                let typ = ctx.deref(typ, "index access to")?;
                 let Type::Array(inner_typ) = typ.clone() else {
                     return None;
                 };
-                let addr = Box::new(address_calc!(array, index, typ.clone()));
-                Expr::Peek(addr, *inner_typ).compile(ctx)?
+                if ctx.is_object(&typ)? {
+                    let [array, index] = [array.compile(ctx)?, index.compile(ctx)?];
+                    format!("(call $get.{} {array} {index})", ctx.layout(&typ)?)
+                } else {
+                    let addr = ctx.element(array, index)?;
+                    format!("({}.load {addr})", inner_typ.compile(ctx)?)
+                }
             }
-            Expr::Field(expr, key) => {
-                let typ = expr.infer(ctx)?;
+            Expr::Field(expr, key, _) => {
+                // Alias is resolved as in the inference, as an instance may return one
+                let typ = expr.infer(ctx)?.infer(ctx)?;
+                let typ = ctx.deref(typ, "field access to")?;
                 let Type::Dict(dict) = typ.clone() else {
                     return None;
                 };
-                let inner_typ = dict.get(key)?.clone();
-                let offset = dict.get_index_of(key)? as i32 * BYTES;
-                let addr = offset_calc!(expr, offset, typ.clone());
-                Expr::Peek(Box::new(addr), inner_typ).compile(ctx)?
+                if ctx.is_object(&typ)? {
+                    let field = dict.get_index_of(key)?;
+                    let object = expr.compile(ctx)?;
+                    let index = ctx.layout(&typ)?;
+                    format!("(struct.get $type.{index} {field} {object})")
+                } else {
+                    let inner_typ = dict.get(key)?.clone();
+                    let offset = dict.get_index_of(key)? as i32 * BYTES;
+                    let addr = ctx.field(expr, offset)?;
+                    format!("({}.load {addr})", inner_typ.compile(ctx)?)
+                }
             }
-            Expr::Block(block) => block.compile(ctx)?,
-            Expr::Clone(from) => format!(
-                "(memory.copy (global.get $allocator) {object} {size}) (call $malloc {size})",
-                size = from.object_size(ctx)?.compile(ctx)?,
-                object = from.compile(ctx)?
-            ),
-            Expr::Peek(expr, typ) => {
+            Expr::Block(block, _) => block.compile(ctx)?,
+            Expr::Clone(from, _) => {
+                let typ = from.infer(ctx)?;
+                if ctx.is_object(&typ)? {
+                    let object = from.compile(ctx)?;
+                    format!("(call $copy.{} {object})", ctx.layout(&typ)?)
+                } else {
+                    let code = format!(
+                        "(call $memcpy {object} {size})",
+                        size = from.object_size(ctx)?.compile(ctx)?,
+                        object = from.compile(ctx)?
+                    );
+                    ctx.adopt(&typ, code)?
+                }
+            }
+            Expr::Free(object, _) => format!("(call $free {})", object.compile(ctx)?),
+            Expr::Peek(expr, typ, _) => {
                 let [typ, addr] = [typ.compile(ctx)?, expr.compile(ctx)?];
                 format!("({typ}.load {addr})")
             }
-            Expr::Poke(addr, expr) => {
+            Expr::Poke(addr, expr, _) => {
                 let typ = expr.infer(ctx)?;
                 let [typ, addr, code] = [typ.compile(ctx)?, addr.compile(ctx)?, expr.compile(ctx)?];
                 format!("({typ}.store {addr} {code})")
             }
-        })
+        };
+        ctx.span = outer;
+        Some(code)
     }
 
     fn infer(&self, ctx: &mut Compiler) -> Option<Type> {
-        Some(match self {
-            Expr::Operator(oper) => oper.infer(ctx)?,
-            Expr::Variable(name) => {
+        let outer = std::mem::replace(&mut ctx.span, self.span());
+        let typ = match self {
+            Expr::Operator(oper, _) => oper.infer(ctx)?,
+            Expr::Variable(name, _) => {
                 if let Some(global) = ctx.global.get(name) {
                     global.clone()
                 } else if let Some(local) = ctx.variable.get(name) {
                     local.clone()
                 } else if let Some(arg) = ctx.argument.get(name) {
                     arg.clone()
+                } else if let Some(captured) = ctx.capture(name) {
+                    captured
+                } else if let Some(function) = ctx.function_type(name) {
+                    // Closure has the fixed signature, while `any` takes a different type at each call
+                    if is_generic(&function) {
+                        ctx.report(format!("can't use generic function `{name}` as a value"));
+                        return None;
+                    }
+                    function
                 } else {
-                    ctx.error = Some(format!("undefined variable `{name}`"));
+                    if !ctx.poisoned.contains(name) {
+                        ctx.report(format!("undefined variable `{name}`"));
+                    }
                     return None;
                 }
             }
-            Expr::Literal(literal) => literal.infer(ctx)?,
-            Expr::Call(name, args) => {
-                if let Some(function) = ctx.function.get(name).or(ctx.export.get(name)).cloned() {
+            Expr::Literal(literal, _) => literal.infer(ctx)?,
+            Expr::Lambda(params, body, _) => ctx.lambda_type(params, body)?,
+            Expr::Call(name, args, _) => {
+                if let Some(typ) = ctx.callee(name) {
+                    let typ = ctx.indirect_type(name, typ, args)?;
+                    ctx.span = self.span();
+                    typ
+                } else if ctx.template.contains_key(name) && ctx.function.contains_key(name) {
+                    let typ = ctx.instance_type(name, args)?;
+                    ctx.span = self.span();
+                    typ
+                } else if let Some(function) =
+                    ctx.function.get(name).or(ctx.export.get(name)).cloned()
+                {
                     arglen_check!(name, args, function.arguments, "function", ctx);
-                    args.iter()
-                        .zip(function.arguments.values())
-                        .map(|(arg, typ)| type_check!(arg, typ, ctx))
-                        .collect::<Option<Vec<_>>>()?;
-                    function.returns.polymorphism(ctx)
+                    let typ = ctx.call_type(&function, args)?;
+                    ctx.span = self.span();
+                    typ
                 } else {
-                    ctx.error = Some(format!("function `{name}` you want to call is not defined"));
+                    if !ctx.poisoned.contains(name) {
+                        ctx.report(format!("function `{name}` you want to call is not defined"));
+                    }
                     return None;
                 }
             }
-            Expr::Macro(name, args) => {
+            Expr::Macro(name, args, _) => {
                 if let Some((params, expr)) = ctx.module.get(name).cloned() {
                     arglen_check!(name, args, params, "macro", ctx);
                     let var_ctx = ctx.alias.clone();
@@ -230,77 +201,386 @@ impl Node for Expr {
                         let typ = arg.infer(ctx)?;
                         ctx.alias.insert(params.to_owned(), typ);
                     }
-                    let typ = expr.infer(ctx)?;
+                    let typ = expr.infer(ctx);
                     ctx.alias = var_ctx;
-                    typ.polymorphism(ctx)
+                    typ?
                 } else {
-                    ctx.error = Some(format!("macro `{name}` you want to call is not defined"));
+                    ctx.report(format!("macro `{name}` you want to call is not defined"));
                     return None;
                 }
             }
-            Expr::Index(arr, _) => {
+            Expr::Index(arr, index, _) => {
                 let infered = arr.infer(ctx)?;
+                let infered = ctx.deref(infered, "index access to")?;
                 let Some(Type::Array(typ)) = infered.infer(ctx) else {
-                    let error_message = format!("can't index access to {}", infered.format());
-                    ctx.error = Some(error_message);
+                    ctx.report(format!("can't index access to {}", infered.format()));
                     return None;
                 };
+                ctx.check_index(arr, index)?;
                 typ.infer(ctx)?
             }
-            Expr::Field(dict, key) => {
+            Expr::Field(dict, key, _) => {
                 let infered = dict.infer(ctx)?.infer(ctx)?;
+                let infered = ctx.deref(infered, "field access to")?;
                 if let Type::Dict(dict) = infered.clone() {
                     let Some(typ) = dict.get(key) else {
-                        let error_message = format!("{} haven't field `{key}`", infered.format());
-                        ctx.error = Some(error_message);
+                        ctx.report(format!("{} haven't field `{key}`", infered.format()));
                         return None;
                     };
-                    typ.infer(ctx)?
+                    match field_path(self).and_then(|path| ctx.narrowed.get(&path)) {
+                        Some(narrowed) => narrowed.clone(),
+                        None => typ.infer(ctx)?,
+                    }
                 } else {
-                    let error_message = format!("can't field access to {}", infered.format());
-                    ctx.error = Some(error_message);
+                    ctx.report(format!("can't field access to {}", infered.format()));
                     return None;
                 }
             }
-            Expr::Block(block) => block.infer(ctx)?,
-            Expr::Clone(from) => {
+            Expr::Block(block, _) => block.infer(ctx)?,
+            Expr::Clone(from, _) => {
                 let typ = from.infer(ctx)?;
-                if is_ptr!(typ, ctx) {
+                let typ = ctx.deref(typ, "memory copy")?;
+                if let Type::Function(..) = typ.infer(ctx)? {
+                    ctx.report("can't memory copy closure");
+                    return None;
+                } else if is_ptr!(typ, ctx) {
                     typ
                 } else {
-                    let errmsg = "can't memory copy primitive typed value";
-                    ctx.error = Some(errmsg.to_string());
+                    ctx.report("can't memory copy primitive typed value");
+                    return None;
+                }
+            }
+            Expr::Free(object, _) => {
+                if ctx.gc != Gc::None {
+                    ctx.report("can't free explicitly while garbage collection manages memory");
                     return None;
                 }
+                let typ = object.infer(ctx)?;
+                if !is_ptr!(typ, ctx) {
+                    ctx.report("can't free primitive typed value");
+                    return None;
+                }
+                Type::Void
             }
-            Expr::Peek(expr, typ) => {
+            Expr::Peek(expr, typ, _) => {
                 expr.infer(ctx)?;
                 typ.clone()
             }
-            Expr::Poke(addr, expr) => {
+            Expr::Poke(addr, expr, _) => {
                 addr.infer(ctx)?;
                 expr.infer(ctx)?;
                 Type::Void
             }
-        })
+        };
+        ctx.span = outer;
+        Some(typ)
     }
 }
 
 impl Expr {
+    /// Binary operations whose operators bind tighter than the level `min`
+    fn parse_binary(p: &mut Parser, min: usize) -> Option<Expr> {
+        let start = p.index;
+        let mut expr = Expr::parse_unary(p)?;
+        let mut last_level = None;
+        while let Some(Token::Operator(op)) = p.peek().cloned()
+            && let Some((level, assoc)) = Op::precedence(op)
+            && level > min
+        {
+            if assoc == Assoc::NonAssoc && last_level == Some(level) {
+                let note = "use parentheses or `&&` to combine comparisons";
+                let err = Diagnostic::error("comparison operators can't be chained", p.span());
+                p.errors.push(err.note(note));
+                return None;
+            }
+            p.advance();
+            // Operands of the same level are grouped to the left
+            let oper = match Op::binary(op) {
+                Some(oper) => oper(expr, Expr::parse_binary(p, level)?),
+                None => Op::Cast(expr, Type::parse(p)?),
+            };
+            expr = Expr::Operator(Box::new(oper), p.span_from(start));
+            last_level = Some(level);
+        }
+        Some(expr)
+    }
+
+    /// Prefix operators, which bind looser than suffix ones
+    fn parse_unary(p: &mut Parser) -> Option<Expr> {
+        let start = p.index;
+        // Negative literal `-1`, unless it's an operand of accesses like `-1.5.floor()`
+        let is_literal = matches!(
+            p.peek_at(1),
+            Some(
+                Token::Int(_)
+                    | Token::Float(_)
+                    | Token::Long(_)
+                    | Token::Double(_)
+                    | Token::Unsigned(_)
+            )
+        ) && !matches!(
+            p.peek_at(2),
+            Some(Token::Punct('.' | '[' | '(' | '#') | Token::Operator("?" | "!"))
+        );
+        if p.peek() == Some(&Token::Operator("-")) && is_literal {
+            Some(Expr::Literal(Value::parse(p)?, p.span_from(start)))
+        } else if let Some(Token::Operator(op)) = p.peek()
+            && let Some(oper) = Op::unary(op)
+        {
+            p.advance();
+            let term = Expr::parse_unary(p)?;
+            Some(Expr::Operator(Box::new(oper(term)), p.span_from(start)))
+        } else {
+            Expr::parse_suffix(p)
+        }
+    }
+
+    /// Suffix operators `?` and `!`
+    fn parse_suffix(p: &mut Parser) -> Option<Expr> {
+        let start = p.index;
+        let mut expr = Expr::parse_postfix(p)?;
+        loop {
+            let oper = match p.peek() {
+                Some(Token::Operator("?")) => Op::NullCheck(expr),
+                Some(Token::Operator("!")) => Op::Nullable(p.reparse(start, "type before `!`")?),
+                _ => return Some(expr),
+            };
+            p.advance();
+            expr = Expr::Operator(Box::new(oper), p.span_from(start));
+        }
+    }
+
+    /// Term followed by accesses and calls
+    fn parse_postfix(p: &mut Parser) -> Option<Expr> {
+        let start = p.index;
+        let mut expr = Expr::parse_term(p)?;
+        loop {
+            expr = match p.peek() {
+                // Dictionary access `dict.field`
+                Some(Token::Punct('.')) => {
+                    p.advance();
+                    let field = p.ident()?;
+                    Expr::Field(Box::new(expr), field, p.span_from(start))
+                }
+                // Index access `array[index]`
+                Some(Token::Punct('[')) => {
+                    let open = p.expect(Token::Punct('['))?;
+                    let index = Expr::parse(p)?;
+                    p.close(open, ']')?;
+                    Expr::Index(Box::new(expr), Box::new(index), p.span_from(start))
+                }
+                // Function call `name(args, ...)` or method call `object.name(args, ...)`
+                Some(Token::Punct('(')) => {
+                    let open = p.expect(Token::Punct('('))?;
+                    let args = p.list(open, ')', Expr::parse)?;
+                    let span = p.span_from(start);
+                    let (name, args) = match expr {
+                        Expr::Variable(name, _) => (name, args),
+                        Expr::Field(obj, name, _) => (name, [vec![*obj], args].concat()),
+                        expr => return p.error("expected function name", expr.span()),
+                    };
+                    if name == "memcpy" {
+                        let [obj] = args.as_slice() else {
+                            return p.error("`memcpy` takes exactly one argument", span);
+                        };
+                        Expr::Clone(Box::new(obj.clone()), span)
+                    } else if name == "free" {
+                        let [obj] = args.as_slice() else {
+                            return p.error("`free` takes exactly one argument", span);
+                        };
+                        Expr::Free(Box::new(obj.clone()), span)
+                    } else {
+                        Expr::Call(name, args, span)
+                    }
+                }
+                // Macro call `name!(types, ...)`
+                Some(Token::Operator("!")) if p.peek_at(1) == Some(&Token::Punct('(')) => {
+                    let Expr::Variable(name, _) = &expr else {
+                        return p.error("expected macro name", expr.span());
+                    };
+                    let name = name.clone();
+                    p.advance();
+                    let open = p.expect(Token::Punct('('))?;
+                    let args = p.list(open, ')', Type::parse)?;
+                    Expr::Macro(name, args, p.span_from(start))
+                }
+                // Enumerate access `( a | b )#a`, with the fields like `( a(int) | b )#a(1)`
+                Some(Token::Punct('#')) => {
+                    let typ = p.reparse(start, "type before `#`")?;
+                    p.advance();
+                    let key = p.ident()?;
+                    let args = match p.eat(Token::Punct('(')) {
+                        Some(open) => p.list(open, ')', Expr::parse)?,
+                        None => vec![],
+                    };
+                    Expr::Literal(Value::Enum(typ, key, args), p.span_from(start))
+                }
+                _ => return Some(expr),
+            };
+        }
+    }
+
+    fn parse_term(p: &mut Parser) -> Option<Expr> {
+        let start = p.index;
+        match p.peek().cloned() {
+            // Formatted string (f-string)
+            Some(Token::FStr(text)) => {
+                p.advance();
+                let span = p.span_from(start);
+                let Some(str) = str_format(&text) else {
+                    return p.error("unbalanced braces in formatted string", span);
+                };
+                let mut result: Option<Expr> = None;
+                let mut offset = span.start + 2;
+                macro_rules! concat {
+                    ($expr: expr) => {{
+                        let expr = $expr;
+                        Some(if let Some(result) = result {
+                            let span = result.span().to(expr.span());
+                            Expr::Operator(Box::new(Op::Add(result, expr)), span)
+                        } else {
+                            expr
+                        })
+                    }};
+                }
+                for elm in str {
+                    let span = Span::new(offset, offset + elm.len());
+                    result = if elm.starts_with("{") && elm.ends_with("}") {
+                        let inner = elm.get(1..elm.len() - 1)?;
+                        let block = Expr::Block(p.embed(inner, offset + 1)?, span);
+                        concat!(Expr::Operator(
+                            Box::new(Op::Cast(block, Type::String)),
+                            span
+                        ))
+                    } else {
+                        concat!(Expr::Literal(Value::String(elm.clone()), span))
+                    };
+                    offset += elm.len();
+                }
+                // The whole f-string is the span of the root, to be formatted as written
+                Some(match result {
+                    Some(Expr::Operator(oper, _)) => Expr::Operator(oper, span),
+                    Some(Expr::Literal(value, _)) => Expr::Literal(value, span),
+                    _ => Expr::Literal(Value::String(String::new()), span),
+                })
+            }
+            // Prioritize expression `(expr)`
+            Some(Token::Punct('(')) => {
+                let open = p.expect(Token::Punct('('))?;
+                let expr = Expr::parse(p)?;
+                p.close(open, ')')?;
+                Some(expr)
+            }
+            // Lambda `fn(arg: type, ...) => body`
+            Some(Token::Keyword("fn")) => {
+                p.advance();
+                let open = p.expect(Token::Punct('('))?;
+                let params = p.list(open, ')', Expr::parse)?;
+                p.expect(Token::Operator("=>"))?;
+                let body = Expr::parse(p)?;
+                Some(Expr::Lambda(params, Box::new(body), p.span_from(start)))
+            }
+            // Code block `{ stmt; ... }`
+            Some(Token::Punct('{')) => {
+                let open = p.expect(Token::Punct('{'))?;
+                let block = Block::parse(p);
+                p.close(open, '}')?;
+                Some(Expr::Block(block?, p.span_from(start)))
+            }
+            // Literal value
+            Some(
+                Token::Int(_)
+                | Token::Float(_)
+                | Token::Long(_)
+                | Token::Double(_)
+                | Token::Unsigned(_)
+                | Token::Str(_)
+                | Token::Punct('[' | '@'),
+            ) => Some(Expr::Literal(Value::parse(p)?, p.span_from(start))),
+            Some(Token::Ident(name)) if name == "true" || name == "false" => {
+                Some(Expr::Literal(Value::parse(p)?, p.span_from(start)))
+            }
+            // Generic type before `!` or `#`, that's parsed again as the type
+            Some(Token::Ident(name)) if let Some(count) = Expr::generic_type(p) => {
+                (0..count).for_each(|_| p.advance());
+                Some(Expr::Variable(name, p.span_from(start)))
+            }
+            // Variable reference
+            Some(Token::Ident(name)) => {
+                p.advance();
+                Some(Expr::Variable(name, p.span_from(start)))
+            }
+            _ => p.expected("expression"),
+        }
+    }
+
+    /// Token count of the generic type like `List<int>` if `!` or `#` follows it,
+    /// as `<` is otherwise a comparison
+    fn generic_type(p: &Parser) -> Option<usize> {
+        if p.peek_at(1) != Some(&Token::Operator("<")) {
+            return None;
+        }
+        let (mut depth, mut ahead) = (0, 1);
+        while ahead == 1 || depth > 0 {
+            depth += match p.peek_at(ahead)? {
+                Token::Operator("<") => 1,
+                Token::Operator(">") => -1,
+                Token::Operator(">>") => -2,
+                Token::Ident(_)
+                | Token::Punct(',' | '[' | ']' | '(' | ')' | '@' | '{' | '}')
+                | Token::Operator(":" | "?" | "->" | "|") => 0,
+                _ => return None,
+            };
+            ahead += 1;
+        }
+        let next = p.peek_at(ahead)?;
+        (depth == 0 && matches!(next, Token::Operator("!") | Token::Punct('#'))).then_some(ahead)
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal(_, span)
+            | Expr::Variable(_, span)
+            | Expr::Operator(_, span)
+            | Expr::Call(_, _, span)
+            | Expr::Macro(_, _, span)
+            | Expr::Index(_, _, span)
+            | Expr::Field(_, _, span)
+            | Expr::Block(_, span)
+            | Expr::Clone(_, span)
+            | Expr::Free(_, span)
+            | Expr::Peek(_, _, span)
+            | Expr::Poke(_, _, span)
+            | Expr::Lambda(_, _, span) => *span,
+        }
+    }
+
     pub fn object_size(&self, ctx: &mut Compiler) -> Option<Expr> {
-        let typ = self.infer(ctx)?;
+        let (typ, span) = (self.infer(ctx)?, self.span());
+        let int = |n| Expr::Literal(Value::Integer(n), span);
         match typ {
-            Type::Dict(dict) => Some(Expr::Literal(Value::Integer(dict.len() as i32 * BYTES))),
-            Type::Array(_) => Some(Expr::Operator(Box::new(Op::Add(
-                Expr::Operator(Box::new(Op::Mul(
-                    Expr::Literal(Value::Integer(BYTES)),
-                    Expr::Peek(
-                        Box::new(Expr::Operator(Box::new(Op::Transmute(self.clone(), typ)))),
-                        Type::Integer,
+            Type::Dict(dict) => Some(int(dict.len() as i32 * BYTES)),
+            Type::Enum(variants) => Some(int(enum_size(&variants))),
+            Type::Array(_) => Some(Expr::Operator(
+                Box::new(Op::Add(
+                    Expr::Operator(
+                        Box::new(Op::Mul(
+                            int(BYTES),
+                            Expr::Peek(
+                                Box::new(Expr::Operator(
+                                    Box::new(Op::Transmute(self.clone(), typ)),
+                                    span,
+                                )),
+                                Type::Integer,
+                                span,
+                            ),
+                        )),
+                        span,
                     ),
-                ))),
-                Expr::Literal(Value::Integer(BYTES)),
-            )))),
+                    int(BYTES),
+                )),
+                span,
+            )),
             _ => None,
         }
     }
@@ -0,0 +1,406 @@
+use crate::{generic::function_name, *};
+
+const INDENT: &str = "    ";
+
+/// Binding strength of prefix operators and negative literals
+const PREFIX: usize = 11;
+/// Binding strength of suffix operators `?` and `!`
+const SUFFIX: usize = 12;
+/// Binding strength of terms and their accesses
+const POSTFIX: usize = 13;
+
+/// Format the source code into the canonical style, keeping its comments
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let mut parser = Parser::new(source, 0);
+    let block = parser.parse_all::<Block>()?;
+    let mut formatter = Formatter {
+        source,
+        comments: parser.comments,
+        next: 0,
+        last: 0,
+    };
+    let mut code = formatter.block(&block, source.len(), 0);
+    code.push('\n');
+    Ok(code)
+}
+
+/// Pretty-printer that turns the syntax tree back into source code
+struct Formatter<'a> {
+    source: &'a str,
+    comments: Vec<(String, Span)>,
+    /// Index of the comment that isn't printed yet
+    next: usize,
+    /// End position of what's printed last
+    last: usize,
+}
+
+impl Formatter<'_> {
+    /// Statements on each line, and comments before the position `end`
+    fn block(&mut self, block: &Block, end: usize, depth: usize) -> String {
+        let pad = INDENT.repeat(depth);
+        let mut lines: Vec<String> = vec![];
+        for (n, stmt) in block.0.iter().enumerate() {
+            let span = stmt.span();
+            self.comments_before(span.start, &pad, &mut lines);
+            self.blank_line(span.start, &mut lines);
+            let mut line = format!("{pad}{}", self.stmt(stmt, depth));
+            if n + 1 != block.0.len() {
+                line.push(';');
+            }
+            self.last = span.end;
+            // Comment that follows the statement on the same line, before the next one
+            let next = block.0.get(n + 1).map_or(end, |stmt| stmt.span().start);
+            if let Some((comment, span)) = self.comments.get(self.next)
+                && span.start < next
+                && let Some(gap) = self.source.get(self.last..span.start)
+                && !gap.contains('\n')
+            {
+                line.push_str(&format!(" {comment}"));
+                self.last = span.end;
+                self.next += 1;
+            }
+            lines.push(line);
+        }
+        self.comments_before(end, &pad, &mut lines);
+        lines.join("\n")
+    }
+
+    fn comments_before(&mut self, pos: usize, pad: &str, lines: &mut Vec<String>) {
+        while let Some((comment, span)) = self.comments.get(self.next)
+            && span.start < pos
+        {
+            self.blank_line(span.start, lines);
+            lines.push(format!("{pad}{comment}"));
+            self.last = span.end;
+            self.next += 1;
+        }
+    }
+
+    /// Keep one blank line where the source code has any
+    fn blank_line(&self, pos: usize, lines: &mut Vec<String>) {
+        let gap = self.source.get(self.last..pos).unwrap_or_default();
+        if !lines.is_empty() && gap.matches('\n').count() > 1 {
+            lines.push(String::new());
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, depth: usize) -> String {
+        match stmt {
+            Stmt::Expr(expr, _) => self.expr(expr, depth),
+            Stmt::Let(scope, name, value, span) => {
+                let scope = match scope {
+                    Scope::Global => "pub ",
+                    Scope::Local => "",
+                };
+                // Compound assignment `let x + 1` shares its span with the statement
+                if let Expr::Operator(oper, oper_span) = value
+                    && oper_span == span
+                    && let Some((op, _, rhs)) = binary(oper)
+                {
+                    let level = Op::precedence(op).map_or(0, |(level, _)| level);
+                    let rhs = self.operand(rhs, level + 1, depth);
+                    return format!("{scope}let {} {op} {rhs}", self.expr(name, depth));
+                }
+                let name = self.expr(name, depth);
+                format!("{scope}let {name} = {}", self.expr(value, depth))
+            }
+            Stmt::If(cond, then, r#else, _) => {
+                let cond = self.expr(cond, depth);
+                let mut code = format!("if {cond} then {}", self.expr(then, depth));
+                if let Some(r#else) = r#else {
+                    // `else` follows the closing brace, or starts a line after a plain branch
+                    if matches!(then, Expr::Block(..)) {
+                        code.push_str(" else ");
+                    } else {
+                        code.push_str(&format!("\n{}else ", INDENT.repeat(depth)));
+                    }
+                    code.push_str(&self.stmt(r#else, depth));
+                }
+                code
+            }
+            Stmt::Match(expr, arms, _) => {
+                let expr = self.expr(expr, depth);
+                let pad = INDENT.repeat(depth + 1);
+                let arms: Vec<_> = arms
+                    .iter()
+                    .map(|(pattern, body)| {
+                        let pattern = match pattern {
+                            Some(pattern) => self.expr(pattern, depth + 1),
+                            None => "_".to_string(),
+                        };
+                        format!("{pad}{pattern} => {}", self.expr(body, depth + 1))
+                    })
+                    .collect();
+                format!(
+                    "match {expr} {{\n{}\n{}}}",
+                    arms.join(",\n"),
+                    INDENT.repeat(depth)
+                )
+            }
+            Stmt::While(cond, body, _) => {
+                let cond = self.expr(cond, depth);
+                format!("while {cond} loop {}", self.expr(body, depth))
+            }
+            Stmt::Generic(scope, params, target, value, _) => {
+                let scope = match scope {
+                    Scope::Global => "pub ",
+                    Scope::Local => "",
+                };
+                // Type parameters are put between the name and the arguments
+                let name = function_name(target).map_or("", String::as_str);
+                let target = self.expr(target, depth);
+                let args = target.strip_prefix(name).unwrap_or(&target);
+                let value = self.expr(value, depth);
+                format!("{scope}let {name}<{}>{args} = {value}", params.join(", "))
+            }
+            Stmt::Type(name, params, typ, _) if params.is_empty() => {
+                format!("type {name} = {}", typ.format())
+            }
+            Stmt::Type(name, params, typ, _) => {
+                format!("type {name}<{}> = {}", params.join(", "), typ.format())
+            }
+            Stmt::Try(expr, catch, _) => {
+                let expr = self.expr(expr, depth);
+                format!("try {expr} catch {}", self.stmt(catch, depth))
+            }
+            Stmt::Module(name, args, value, _) => {
+                let value = self.expr(value, depth);
+                format!("module {name}({}) = {value}", args.join(", "))
+            }
+            Stmt::Overload(id, (lhs, rhs), name, _) => {
+                let table = Op::overload_id_table();
+                let op = table
+                    .iter()
+                    .find(|(_, n)| *n == id)
+                    .map(|(op, _)| op.as_str())
+                    .unwrap_or_default();
+                if op == "neg" {
+                    format!("overload {name} = -{}", lhs.format())
+                } else {
+                    format!("overload {name} = {} {op} {}", lhs.format(), rhs.format())
+                }
+            }
+            Stmt::Import((name, args, ret), _) => {
+                let args: Vec<_> = args.iter().map(Type::format).collect();
+                format!("import {name}({}): {}", args.join(", "), ret.format())
+            }
+            Stmt::Return(Some(value), _) => format!("return {}", self.expr(value, depth)),
+            Stmt::Return(None, _) => "return".to_string(),
+            Stmt::Break(_) => "break".to_string(),
+            Stmt::Next(_) => "next".to_string(),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, depth: usize) -> String {
+        // Formatted string is kept as written, since it's desugared in parsing
+        // Literal is kept as written, like `0xFF`, `1_000` or the f-string
+        let text = self.source.get(expr.span().start..expr.span().end);
+        if let Some(text) = text
+            && let Ok(tokens) = tokenize(text, 0)
+            && is_written(&tokens)
+        {
+            return text.to_string();
+        }
+        match expr {
+            Expr::Literal(value, _) => self.value(value, depth),
+            Expr::Variable(name, _) => name.clone(),
+            Expr::Operator(oper, _) => self.oper(oper, depth),
+            Expr::Call(name, args, span) => match args.split_first() {
+                Some((obj, rest)) if self.is_method(name, *span) => {
+                    let obj = self.operand(obj, POSTFIX, depth);
+                    format!("{obj}.{name}({})", self.list(rest, depth))
+                }
+                _ => format!("{name}({})", self.list(args, depth)),
+            },
+            Expr::Macro(name, args, _) => {
+                let args: Vec<_> = args.iter().map(Type::format).collect();
+                format!("{name}!({})", args.join(", "))
+            }
+            Expr::Index(array, index, _) => {
+                let array = self.operand(array, POSTFIX, depth);
+                format!("{array}[{}]", self.expr(index, depth))
+            }
+            Expr::Field(obj, name, _) => format!("{}.{name}", self.operand(obj, POSTFIX, depth)),
+            Expr::Block(block, span) => {
+                let inner = self.block(block, span.end - 1, depth + 1);
+                self.last = span.end;
+                format!("{{\n{inner}\n{}}}", INDENT.repeat(depth))
+            }
+            Expr::Clone(obj, span) if self.is_method("memcpy", *span) => {
+                format!("{}.memcpy()", self.operand(obj, POSTFIX, depth))
+            }
+            Expr::Clone(obj, _) => format!("memcpy({})", self.expr(obj, depth)),
+            Expr::Free(obj, span) if self.is_method("free", *span) => {
+                format!("{}.free()", self.operand(obj, POSTFIX, depth))
+            }
+            Expr::Free(obj, _) => format!("free({})", self.expr(obj, depth)),
+            // Internal nodes that are only made in compilation
+            Expr::Peek(addr, typ, _) => {
+                format!("peek({}, {})", self.expr(addr, depth), typ.format())
+            }
+            Expr::Poke(addr, value, _) => {
+                let addr = self.expr(addr, depth);
+                format!("poke({addr}, {})", self.expr(value, depth))
+            }
+            Expr::Lambda(params, body, _) => {
+                let params = self.list(params, depth);
+                format!("fn({params}) => {}", self.expr(body, depth))
+            }
+        }
+    }
+
+    fn oper(&mut self, oper: &Op, depth: usize) -> String {
+        if let Some((op, lhs, rhs)) = binary(oper) {
+            let (level, assoc) = Op::precedence(op).unwrap_or((0, Assoc::Left));
+            // Operands of the same level are grouped to the left, unless they can't chain
+            let left = if assoc == Assoc::NonAssoc {
+                level + 1
+            } else {
+                level
+            };
+            let lhs = self.operand(lhs, left, depth);
+            return format!("{lhs} {op} {}", self.operand(rhs, level + 1, depth));
+        }
+        match oper {
+            // Negation of a literal isn't folded into the negative literal
+            Op::Neg(
+                term @ Expr::Literal(
+                    Value::Integer(_)
+                    | Value::Number(_)
+                    | Value::Long(_)
+                    | Value::Double(_)
+                    | Value::Unsigned(_),
+                    _,
+                ),
+            ) => {
+                format!("-({})", self.expr(term, depth))
+            }
+            Op::Neg(term) => match self.operand(term, PREFIX, depth) {
+                term if term.starts_with('-') => format!("-({term})"),
+                term => format!("-{term}"),
+            },
+            Op::BNot(term) => format!("~{}", self.operand(term, PREFIX, depth)),
+            Op::LNot(term) => format!("!{}", self.operand(term, PREFIX, depth)),
+            Op::Cast(term, typ) => format!("{}: {}", self.operand(term, 10, depth), typ.format()),
+            Op::NullCheck(term) => format!("{}?", self.operand(term, POSTFIX, depth)),
+            Op::Nullable(typ) => format!("{}!", typ.format()),
+            Op::Transmute(term, typ) => {
+                format!("transmute({}, {})", self.expr(term, depth), typ.format())
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// Whether the call is written as method `obj.name(args)`, not `name(obj, args)`
+    fn is_method(&self, name: &str, span: Span) -> bool {
+        let rest = self.source.get(span.start..).unwrap_or_default();
+        !rest
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.trim_start().starts_with(['(', '<']))
+    }
+
+    /// Operand that's parenthesized if it binds looser than `level`
+    fn operand(&mut self, expr: &Expr, level: usize, depth: usize) -> String {
+        let code = self.expr(expr, depth);
+        if strength(expr) < level {
+            format!("({code})")
+        } else {
+            code
+        }
+    }
+
+    fn list(&mut self, items: &[Expr], depth: usize) -> String {
+        let items: Vec<_> = items.iter().map(|item| self.expr(item, depth)).collect();
+        items.join(", ")
+    }
+
+    fn value(&mut self, value: &Value, depth: usize) -> String {
+        match value {
+            Value::Integer(n) => n.to_string(),
+            // Number keeps its decimal point not to be an integer
+            Value::Number(n) if n.fract() == 0.0 => format!("{n:.1}"),
+            Value::Number(n) => n.to_string(),
+            Value::Long(n) => format!("{n}L"),
+            Value::Double(n) => format!("{n}D"),
+            Value::Unsigned(n) => format!("{n}U"),
+            Value::Bool(b) => b.to_string(),
+            Value::String(text) => format!("\"{text}\""),
+            Value::Array(items) => format!("[{}]", self.list(items, depth)),
+            Value::Dict(dict) if dict.is_empty() => "@{}".to_string(),
+            Value::Dict(dict) => {
+                let fields: Vec<_> = dict
+                    .iter()
+                    .map(|(key, value)| format!("{key}: {}", self.expr(value, depth)))
+                    .collect();
+                format!("@{{ {} }}", fields.join(", "))
+            }
+            Value::Enum(typ, key, args) if args.is_empty() => format!("{}#{key}", typ.format()),
+            Value::Enum(typ, key, args) => {
+                format!("{}#{key}({})", typ.format(), self.list(args, depth))
+            }
+        }
+    }
+}
+
+/// Whether the tokens are a literal, whose value may be written in several ways
+fn is_written(tokens: &[(Token, Span)]) -> bool {
+    let ([(token, _)] | [(Token::Operator("-"), _), (token, _)]) = tokens else {
+        return false;
+    };
+    matches!(
+        token,
+        Token::Int(_)
+            | Token::Float(_)
+            | Token::Long(_)
+            | Token::Double(_)
+            | Token::Unsigned(_)
+            | Token::Str(_)
+            | Token::FStr(_)
+    )
+}
+
+/// Operator token and operands of the binary operation
+fn binary(oper: &Op) -> Option<(&'static str, &Expr, &Expr)> {
+    Some(match oper {
+        Op::Add(lhs, rhs) => ("+", lhs, rhs),
+        Op::Sub(lhs, rhs) => ("-", lhs, rhs),
+        Op::Mul(lhs, rhs) => ("*", lhs, rhs),
+        Op::Div(lhs, rhs) => ("/", lhs, rhs),
+        Op::Mod(lhs, rhs) => ("%", lhs, rhs),
+        Op::Shr(lhs, rhs) => (">>", lhs, rhs),
+        Op::Shl(lhs, rhs) => ("<<", lhs, rhs),
+        Op::Eql(lhs, rhs) => ("==", lhs, rhs),
+        Op::Neq(lhs, rhs) => ("!=", lhs, rhs),
+        Op::Lt(lhs, rhs) => ("<", lhs, rhs),
+        Op::Gt(lhs, rhs) => (">", lhs, rhs),
+        Op::LtEq(lhs, rhs) => ("<=", lhs, rhs),
+        Op::GtEq(lhs, rhs) => (">=", lhs, rhs),
+        Op::BAnd(lhs, rhs) => ("&", lhs, rhs),
+        Op::BOr(lhs, rhs) => ("|", lhs, rhs),
+        Op::XOr(lhs, rhs) => ("^", lhs, rhs),
+        Op::LAnd(lhs, rhs) => ("&&", lhs, rhs),
+        Op::LOr(lhs, rhs) => ("||", lhs, rhs),
+        _ => return None,
+    })
+}
+
+/// How tightly the expression binds, to parenthesize it as an operand
+fn strength(expr: &Expr) -> usize {
+    match expr {
+        Expr::Operator(oper, _) => match oper.as_ref() {
+            Op::Neg(_) | Op::BNot(_) | Op::LNot(_) => PREFIX,
+            Op::NullCheck(_) | Op::Nullable(_) => SUFFIX,
+            Op::Cast(..) => 10,
+            oper => binary(oper)
+                .and_then(|(op, _, _)| Op::precedence(op))
+                .map_or(POSTFIX, |(level, _)| level),
+        },
+        Expr::Literal(Value::Integer(n), _) if *n < 0 => PREFIX,
+        Expr::Literal(Value::Number(n), _) if n.is_sign_negative() => PREFIX,
+        Expr::Literal(Value::Long(n), _) if *n < 0 => PREFIX,
+        Expr::Literal(Value::Double(n), _) if n.is_sign_negative() => PREFIX,
+        // Body of the lambda takes the rest of the expression
+        Expr::Lambda(..) => 0,
+        _ => POSTFIX,
+    }
+}
@@ -0,0 +1,606 @@
+use crate::*;
+
+/// Strategy to reclaim heap objects that are no longer used
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Gc {
+    /// Objects are only freed explicitly
+    #[default]
+    None,
+    /// Reference counting, in the block header above the size class
+    Rc,
+    /// Tracing from the globals and the shadow stack of pointer typed locals
+    MarkSweep,
+    /// Structs and arrays of the Wasm GC proposal, that the host engine traces
+    Wasm,
+}
+
+/// Byte size of the shadow stack, whose slot is a pointer and its layout
+pub(crate) const SHADOW_STACK: i32 = 65536;
+/// Byte size of the worklist of objects to trace, whose entry is a pointer, its layout,
+/// and the index of the next element if it's an array
+pub(crate) const WORKLIST: i32 = 65536;
+
+/// Byte size of the bitmap that marks the static objects below the address, a bit per word
+pub(crate) fn static_marks(end: i32) -> i32 {
+    (end + 32 * WORD - 1) / (32 * WORD) * WORD
+}
+
+impl Compiler {
+    /// Index of the pointer type, whose functions are generated with the module
+    pub(crate) fn layout(&mut self, typ: &Type) -> Option<usize> {
+        let typ = typ.infer(self)?.erase_null();
+        let key = typ.format();
+        if let Some(index) = self.layouts.get_index_of(&key) {
+            return Some(index);
+        }
+        Some(self.layouts.insert_full(key, typ).0)
+    }
+
+    /// Pointer typed arguments and local variables with their layouts, in the order of slots
+    fn slots(&mut self) -> Option<Vec<(String, usize)>> {
+        let mut result = vec![];
+        let locals = self
+            .argument
+            .clone()
+            .into_iter()
+            .chain(self.variable.clone());
+        for (name, typ) in locals {
+            if is_ptr!(typ, self) {
+                result.push((name, self.layout(&typ)?));
+            }
+        }
+        Some(result)
+    }
+
+    /// Set the variable `name` to the value, keeping track of the references to the objects
+    pub(crate) fn assign(
+        &mut self,
+        scope: Scope,
+        name: &str,
+        typ: &Type,
+        value: String,
+    ) -> Option<String> {
+        let keyword = match scope {
+            Scope::Local => "local",
+            Scope::Global => "global",
+        };
+        if !is_ptr!(typ, self) {
+            return Some(format!("({keyword}.set ${name} {value})"));
+        }
+        Some(match (self.gc, scope) {
+            (Gc::Rc, _) => {
+                let index = self.layout(typ)?;
+                let old = format!("({keyword}.get ${name})");
+                format!("({keyword}.set ${name} (call $assign.{index} {old} {value}))")
+            }
+            // Globals are scanned directly, but locals are mirrored in their slots
+            (Gc::MarkSweep, Scope::Local) => {
+                let slot = self.slots()?.iter().position(|(local, _)| local == name)?;
+                format!(
+                    "(local.set ${name} {value}) (i32.store offset={} (local.get $gc.frame) (local.get ${name}))",
+                    slot as i32 * 2 * WORD
+                )
+            }
+            _ => format!("({keyword}.set ${name} {value})"),
+        })
+    }
+
+    /// Store the value into memory, managing reference counts of the old and new one
+    pub(crate) fn store(&mut self, typ: &Type, addr: String, value: String) -> Option<String> {
+        if self.gc == Gc::Rc && is_ptr!(typ, self) {
+            let index = self.layout(typ)?;
+            return Some(format!("(call $store.{index} {addr} {value})"));
+        }
+        Some(format!("({}.store {addr} {value})", typ.compile(self)?))
+    }
+
+    /// Copied object shares the children, which have to be kept as well
+    pub(crate) fn adopt(&mut self, typ: &Type, object: String) -> Option<String> {
+        Some(match self.gc {
+            Gc::None | Gc::Wasm => object,
+            Gc::Rc => format!("(call $adopt.{} {object})", self.layout(typ)?),
+            // Allocator only roots the object itself, so it's rooted again to trace the children
+            Gc::MarkSweep => {
+                let index = self.layout(typ)? + 1;
+                format!("(call $gc.push {object} (i32.const {index}))")
+            }
+        })
+    }
+
+    /// Statement drops the temporary roots that it pushed to the shadow stack
+    pub(crate) fn statement(&self, code: String) -> String {
+        if self.gc != Gc::MarkSweep || code.is_empty() {
+            return code;
+        }
+        format!("(global.set $gc.sp (global.get $gc.sp) {code})")
+    }
+
+    /// Last statement of the block drops its roots as well, and its value passes through them,
+    /// rooted again in the enclosing statement if it's an object
+    pub(crate) fn passing(&mut self, typ: &Type, code: String) -> Option<String> {
+        if self.gc != Gc::MarkSweep || code.is_empty() {
+            return Some(code);
+        }
+        let typ = typ.infer(self)?;
+        if let Type::Void = typ {
+            return Some(self.statement(code));
+        }
+        let value = format!(
+            "(call $gc.pass.{} (global.get $gc.sp) {code})",
+            typ.compile(self)?
+        );
+        if !is_ptr!(typ, self) {
+            return Some(value);
+        }
+        let index = self.layout(&typ)? + 1;
+        Some(format!("(call $gc.push {value} (i32.const {index}))"))
+    }
+
+    /// Code at the start of the function, to own the arguments or push its frame
+    pub(crate) fn prologue(&mut self) -> Option<String> {
+        let mut result = vec![];
+        let slots = self.slots()?;
+        match self.gc {
+            Gc::None | Gc::Wasm => {}
+            // Arguments are owned by the function while it runs, so temporary objects passed are freed
+            Gc::Rc => {
+                for (name, _) in slots {
+                    if self.argument.contains_key(&name) {
+                        result.push(format!("(call $retain (local.get ${name}))"));
+                    }
+                }
+            }
+            Gc::MarkSweep if !slots.is_empty() => {
+                let size = slots.len() as i32 * 2 * WORD;
+                result.push(format!(
+                    "(local.set $gc.frame (call $gc.enter (i32.const {size})))"
+                ));
+                for (slot, (name, index)) in slots.iter().enumerate() {
+                    let offset = slot as i32 * 2 * WORD;
+                    let value = if self.argument.contains_key(name) {
+                        format!("(local.get ${name})")
+                    } else {
+                        "(i32.const -1)".to_string()
+                    };
+                    result.push(format!(
+                        "(i32.store offset={offset} (local.get $gc.frame) {value}) (i32.store offset={} (local.get $gc.frame) (i32.const {}))",
+                        offset + WORD,
+                        index + 1
+                    ));
+                }
+            }
+            Gc::MarkSweep => {}
+        }
+        Some(join!(result))
+    }
+
+    /// Release local variables and arguments before returning the value of the function
+    pub(crate) fn epilogue(&mut self, typ: &Type, code: String) -> Option<String> {
+        let slots = self.slots()?;
+        if matches!(self.gc, Gc::None | Gc::Wasm) || slots.is_empty() {
+            return Some(code);
+        }
+        let typ = typ.infer(self)?;
+        let is_ptr = is_ptr!(typ, self);
+        let (release, returns) = if self.gc == Gc::Rc {
+            let mut release: Vec<_> = slots
+                .iter()
+                .map(|(name, index)| format!("(call $release.{index} (local.get ${name}))"))
+                .collect();
+            // Returned object survives, but isn't owned by anyone until the caller stores it
+            if is_ptr {
+                release.insert(0, "(call $retain (local.get $gc.result))".to_string());
+            }
+            let returns = "(call $unretain (local.get $gc.result))".to_string();
+            (join!(release), returns)
+        } else {
+            // Returned object is rooted in the frame of the caller instead
+            let release = "(global.set $gc.sp (local.get $gc.frame))".to_string();
+            let returns = if is_ptr {
+                let index = self.layout(&typ)? + 1;
+                format!("(call $gc.push (local.get $gc.result) (i32.const {index}))")
+            } else {
+                String::new()
+            };
+            (release, returns)
+        };
+        Some(if let Type::Void = typ {
+            format!("{code} {release}")
+        } else if is_ptr {
+            format!("(local.set $gc.result {code}) {release} {returns}")
+        } else {
+            format!("(local.set $gc.result {code}) {release} (local.get $gc.result)")
+        })
+    }
+
+    /// Host doesn't count references, so the imported function is called through a wrapper
+    /// that owns its arguments, and the name to import the original one as is returned
+    pub(crate) fn wrap_import(&mut self, name: &str, function: &Function) -> Option<String> {
+        if self.gc != Gc::Rc {
+            return Some(name.to_string());
+        }
+        let native = format!("{name}.native");
+        let var_ctx = std::mem::take(&mut self.variable);
+        let arg_ctx = std::mem::replace(&mut self.argument, function.arguments.clone());
+        let mut params = vec![];
+        for (arg, typ) in &function.arguments {
+            params.push(format!("(param ${arg} {})", typ.compile(self)?));
+        }
+        let args = function
+            .arguments
+            .keys()
+            .map(|arg| format!("(local.get ${arg})"));
+        let call = format!("(call ${native} {})", join!(args.collect::<Vec<_>>()));
+        let code = format!(
+            "(func ${name} {params} {ret} {temp} {prologue} {body})",
+            params = join!(params),
+            ret = compile_return!(function.returns, self),
+            temp = self.epilogue_local(&function.returns)?,
+            prologue = self.prologue()?,
+            body = self.epilogue(&function.returns, call)?,
+        );
+        self.variable = var_ctx;
+        self.argument = arg_ctx;
+        self.declare.insert(name.to_string(), code);
+        Some(native)
+    }
+
+    /// Temporary locals that the prologue and epilogue of the function need
+    pub(crate) fn epilogue_local(&mut self, typ: &Type) -> Option<String> {
+        let typ = typ.infer(self)?;
+        let frame = match self.gc {
+            Gc::None | Gc::Wasm => return Some(String::new()),
+            Gc::Rc => "",
+            Gc::MarkSweep => "(local $gc.frame i32)",
+        };
+        if let Type::Void = typ {
+            return Some(frame.to_string());
+        }
+        Some(format!("{frame} (local $gc.result {})", typ.compile(self)?))
+    }
+
+    /// Code that the allocator runs before growing the memory, and after allocating the object
+    pub(crate) fn malloc_hooks(&self) -> (&'static str, &'static str) {
+        if self.gc != Gc::MarkSweep {
+            return ("", "");
+        }
+        (
+            concat!(
+                "(if (i32.eqz (local.get $block)) (then (if (i32.gt_u (i32.add (global.get $allocator) ",
+                "(i32.shl (i32.const 1) (local.get $class))) (i32.mul (memory.size) (i32.const 65536))) (then ",
+                "(call $gc.collect) (local.set $block (i32.load (local.get $head))))))) "
+            ),
+            // Nothing references the object yet, until the statement allocating it ends
+            "(drop (call $gc.push (i32.add (local.get $block) (i32.const 4)) (i32.const 0))) ",
+        )
+    }
+
+    /// Functions to manage memory of the pointer types that the program uses
+    pub(crate) fn expand_gc(&mut self) -> Option<String> {
+        match self.gc {
+            Gc::None => Some(String::new()),
+            Gc::Rc => self.expand_rc(),
+            Gc::MarkSweep => self.expand_mark_sweep(),
+            Gc::Wasm => self.expand_objects(),
+        }
+    }
+
+    fn expand_rc(&mut self) -> Option<String> {
+        let mut result = vec![
+            concat!(
+                "(func $retain (param $ptr i32) (local $block i32) ",
+                "(if (i32.lt_s (local.get $ptr) (global.get $heap)) (then (return))) ",
+                "(local.set $block (i32.sub (local.get $ptr) (i32.const 4))) ",
+                "(i32.store (local.get $block) (i32.add (i32.load (local.get $block)) (i32.const 256))))"
+            )
+            .to_string(),
+            concat!(
+                "(func $unretain (param $ptr i32) (result i32) (local $block i32) ",
+                "(local.set $block (i32.sub (local.get $ptr) (i32.const 4))) ",
+                "(if (i32.ge_s (local.get $ptr) (global.get $heap)) (then ",
+                "(if (i32.ge_u (i32.load (local.get $block)) (i32.const 256)) (then ",
+                "(i32.store (local.get $block) (i32.sub (i32.load (local.get $block)) (i32.const 256))))))) ",
+                "(local.get $ptr))"
+            )
+            .to_string(),
+        ];
+        // Children may add new types while generating
+        let mut index = 0;
+        while index < self.layouts.len() {
+            let typ = self.layouts[index].clone();
+            let release = self.children(&typ, |ctx, child, addr| {
+                Some(format!(
+                    "(call $release.{} (i32.load {addr}))",
+                    ctx.layout(child)?
+                ))
+            })?;
+            let adopt = self.children(&typ, |_, _, addr| {
+                Some(format!("(call $retain (i32.load {addr}))"))
+            })?;
+            result.push(format!(
+                concat!(
+                    "(func $release.{index} (param $ptr i32) (local $block i32) (local $index i32) ",
+                    "(if (i32.lt_s (local.get $ptr) (global.get $heap)) (then (return))) ",
+                    "(local.set $block (i32.sub (local.get $ptr) (i32.const 4))) ",
+                    "(if (i32.ge_u (i32.load (local.get $block)) (i32.const 512)) (then ",
+                    "(i32.store (local.get $block) (i32.sub (i32.load (local.get $block)) (i32.const 256))) (return))) ",
+                    "{release} (call $free (local.get $ptr)))"
+                ),
+                index = index,
+                release = release,
+            ));
+            result.push(format!(
+                concat!(
+                    "(func $adopt.{index} (param $ptr i32) (result i32) (local $index i32) ",
+                    "(if (i32.ge_s (local.get $ptr) (global.get $heap)) (then {adopt})) (local.get $ptr))"
+                ),
+                index = index,
+                adopt = adopt,
+            ));
+            result.push(format!(
+                concat!(
+                    "(func $assign.{index} (param $old i32) (param $new i32) (result i32) ",
+                    "(call $retain (local.get $new)) (call $release.{index} (local.get $old)) (local.get $new))"
+                ),
+                index = index,
+            ));
+            result.push(format!(
+                concat!(
+                    "(func $store.{index} (param $addr i32) (param $new i32) ",
+                    "(i32.store (local.get $addr) (call $assign.{index} (i32.load (local.get $addr)) (local.get $new))))"
+                ),
+                index = index,
+            ));
+            index += 1;
+        }
+        Some(join!(result))
+    }
+
+    /// Header of the block has the mark bit and the free bit above the size class
+    fn expand_mark_sweep(&mut self) -> Option<String> {
+        let (table, stack, heap) = self.regions();
+        let work = stack + SHADOW_STACK;
+        let (marks, size) = (work + WORKLIST, static_marks(table));
+        let mut globals = vec![];
+        for (name, typ) in self.global.clone() {
+            if is_ptr!(typ, self) {
+                let index = self.layout(&typ)?;
+                globals.push(format!("(call $gc.visit.{index} (global.get ${name}))"));
+            }
+        }
+        let mut result = vec![
+            format!("(global $gc.sp (mut i32) (i32.const {stack}))"),
+            format!(
+                concat!(
+                    "(func $gc.push (param $ptr i32) (param $type i32) (result i32) ",
+                    "(if (i32.ge_u (global.get $gc.sp) (i32.const {heap})) (then (call $stack_overflow))) ",
+                    "(i32.store (global.get $gc.sp) (local.get $ptr)) ",
+                    "(i32.store offset=4 (global.get $gc.sp) (local.get $type)) ",
+                    "(global.set $gc.sp (i32.add (global.get $gc.sp) (i32.const 8))) (local.get $ptr))"
+                ),
+                heap = heap
+            ),
+            format!("(global $gc.wp (mut i32) (i32.const {work}))"),
+            // Marked object is traced later from the worklist, as the recursion would exhaust the call stack
+            format!(
+                concat!(
+                    "(func $gc.work (param $ptr i32) (param $type i32) (param $at i32) ",
+                    "(if (i32.gt_u (global.get $gc.wp) (i32.const {limit})) (then (call $stack_overflow))) ",
+                    "(i32.store (global.get $gc.wp) (local.get $ptr)) ",
+                    "(i32.store offset=4 (global.get $gc.wp) (local.get $type)) ",
+                    "(i32.store offset=8 (global.get $gc.wp) (local.get $at)) ",
+                    "(global.set $gc.wp (i32.add (global.get $gc.wp) (i32.const 12))))"
+                ),
+                limit = heap - 12
+            ),
+            format!(
+                concat!(
+                    "(func $gc.enter (param $size i32) (result i32) (local $frame i32) ",
+                    "(local.set $frame (global.get $gc.sp)) ",
+                    "(global.set $gc.sp (i32.add (local.get $frame) (local.get $size))) ",
+                    "(if (i32.gt_u (global.get $gc.sp) (i32.const {heap})) (then (call $stack_overflow))) ",
+                    "(local.get $frame))"
+                ),
+                heap = heap
+            ),
+            // Value of the last statement is kept on the operand stack while its roots are dropped
+            join!(
+                ["i32", "i64", "f32", "f64"]
+                    .map(|typ| format!("(func $gc.pass.{typ} (param $sp i32) (param $value {typ}) (result {typ}) (global.set $gc.sp (local.get $sp)) (local.get $value))"))
+            ),
+            // Static objects have no header, so they're marked in the bitmap instead
+            format!(
+                concat!(
+                    "(func $gc.mark (param $ptr i32) (result i32) (local $block i32) (local $bit i32) ",
+                    "(if (i32.eq (local.get $ptr) (i32.const -1)) (then (return (i32.const 0)))) ",
+                    "(if (i32.lt_s (local.get $ptr) (global.get $heap)) (then ",
+                    "(local.set $block (i32.add (i32.const {marks}) (i32.shl (i32.shr_u (local.get $ptr) (i32.const 7)) (i32.const 2)))) ",
+                    "(local.set $bit (i32.shl (i32.const 1) (i32.shr_u (local.get $ptr) (i32.const 2)))) ",
+                    "(if (i32.and (i32.load (local.get $block)) (local.get $bit)) (then (return (i32.const 0)))) ",
+                    "(i32.store (local.get $block) (i32.or (i32.load (local.get $block)) (local.get $bit))) ",
+                    "(return (i32.const 1)))) ",
+                    "(local.set $block (i32.sub (local.get $ptr) (i32.const 4))) ",
+                    "(if (i32.and (i32.load (local.get $block)) (i32.const 256)) (then (return (i32.const 0)))) ",
+                    "(i32.store (local.get $block) (i32.or (i32.load (local.get $block)) (i32.const 256))) ",
+                    "(i32.const 1))"
+                ),
+                marks = marks
+            ),
+            format!(
+                concat!(
+                    "(func $gc.collect (local $slot i32) (local $block i32) (local $header i32) {globals} ",
+                    "(local.set $slot (i32.const {stack})) ",
+                    "(block $done (loop $each (br_if $done (i32.ge_u (local.get $slot) (global.get $gc.sp))) ",
+                    "(call $gc.scan (i32.load (local.get $slot)) (i32.load offset=4 (local.get $slot))) ",
+                    "(local.set $slot (i32.add (local.get $slot) (i32.const 8))) (br $each))) ",
+                    "(block $done (loop $each (br_if $done (i32.le_u (global.get $gc.wp) (i32.const {work}))) ",
+                    "(global.set $gc.wp (i32.sub (global.get $gc.wp) (i32.const 12))) ",
+                    "(call $gc.trace (i32.load (global.get $gc.wp)) (i32.load offset=4 (global.get $gc.wp)) ",
+                    "(i32.load offset=8 (global.get $gc.wp))) (br $each))) ",
+                    "(local.set $slot (i32.const {marks})) ",
+                    "(block $done (loop $each (br_if $done (i32.ge_u (local.get $slot) (i32.const {end}))) ",
+                    "(i32.store (local.get $slot) (i32.const 0)) ",
+                    "(local.set $slot (i32.add (local.get $slot) (i32.const 4))) (br $each))) ",
+                    "(local.set $block (global.get $heap)) ",
+                    "(block $done (loop $each (br_if $done (i32.ge_u (local.get $block) (global.get $allocator))) ",
+                    "(local.set $header (i32.load (local.get $block))) ",
+                    "(if (i32.and (local.get $header) (i32.const 256)) ",
+                    "(then (i32.store (local.get $block) (i32.xor (local.get $header) (i32.const 256)))) ",
+                    "(else (if (i32.eqz (i32.and (local.get $header) (i32.const 512))) (then ",
+                    "(call $free (i32.add (local.get $block) (i32.const 4))) ",
+                    "(i32.store (local.get $block) (i32.or (local.get $header) (i32.const 512))))))) ",
+                    "(local.set $block (i32.add (local.get $block) ",
+                    "(i32.shl (i32.const 1) (i32.and (local.get $header) (i32.const 255))))) (br $each))))"
+                ),
+                globals = join!(globals),
+                stack = stack,
+                work = work,
+                marks = marks,
+                end = marks + size
+            ),
+        ];
+        // Children may add new types while generating
+        let (mut index, mut traced) = (0, vec![]);
+        while index < self.layouts.len() {
+            let typ = self.layouts[index].clone();
+            let trace = match typ.infer(self)? {
+                // Array is traced an element at a time, so that its entry is put back for the rest
+                Type::Array(element) if is_ptr!(element, self) => format!(
+                    concat!(
+                        "(if (i32.lt_u (local.get $at) (i32.load (local.get $ptr))) (then ",
+                        "(if (i32.lt_u (i32.add (local.get $at) (i32.const 1)) (i32.load (local.get $ptr))) (then ",
+                        "(call $gc.work (local.get $ptr) (i32.const {type}) (i32.add (local.get $at) (i32.const 1))))) ",
+                        "(call $gc.visit.{child} (i32.load (i32.add (local.get $ptr) ",
+                        "(i32.mul (i32.add (local.get $at) (i32.const 1)) (i32.const {bytes})))))))"
+                    ),
+                    type = index + 1,
+                    child = self.layout(&element)?,
+                    bytes = BYTES
+                ),
+                typ => self.children(&typ, |ctx, child, addr| {
+                    Some(format!(
+                        "(call $gc.visit.{} (i32.load {addr}))",
+                        ctx.layout(child)?
+                    ))
+                })?,
+            };
+            if trace.is_empty() {
+                result.push(format!(
+                    "(func $gc.visit.{index} (param $ptr i32) (drop (call $gc.mark (local.get $ptr))))"
+                ));
+            } else {
+                result.push(format!(
+                    "(func $gc.visit.{index} (param $ptr i32) (if (call $gc.mark (local.get $ptr)) (then (call $gc.work (local.get $ptr) (i32.const {}) (i32.const 0)))))",
+                    index + 1
+                ));
+                result.push(format!(
+                    "(func $gc.trace.{index} (param $ptr i32) (param $at i32) {trace})"
+                ));
+                traced.push(index);
+            }
+            index += 1;
+        }
+        let trace: Vec<_> = traced
+            .iter()
+            .map(|index| {
+                format!(
+                    "(if (i32.eq (local.get $type) (i32.const {})) (then (call $gc.trace.{index} (local.get $ptr) (local.get $at))))",
+                    index + 1
+                )
+            })
+            .collect();
+        result.push(format!(
+            "(func $gc.trace (param $ptr i32) (param $type i32) (param $at i32) {})",
+            join!(trace)
+        ));
+        // Slot of the shadow stack has the layout index plus one, or zero to mark only the object
+        let scan: Vec<_> = (0..self.layouts.len())
+            .map(|index| {
+                format!(
+                    "(if (i32.eq (local.get $type) (i32.const {})) (then (call $gc.visit.{index} (local.get $ptr))))",
+                    index + 1
+                )
+            })
+            .collect();
+        result.push(format!(
+            "(func $gc.scan (param $ptr i32) (param $type i32) (if (i32.eqz (local.get $type)) (then (drop (call $gc.mark (local.get $ptr))))) {})",
+            join!(scan)
+        ));
+        Some(join!(result))
+    }
+
+    /// Code that visits each pointer child of the object at `$ptr`, given its type and address
+    fn children(
+        &mut self,
+        typ: &Type,
+        visit: impl Fn(&mut Compiler, &Type, String) -> Option<String>,
+    ) -> Option<String> {
+        Some(match typ.infer(self)? {
+            Type::Dict(dict) => {
+                let mut result = vec![];
+                for (index, field) in dict.values().enumerate() {
+                    if is_ptr!(field, self) {
+                        let offset = index as i32 * BYTES;
+                        let addr = format!("(i32.add (local.get $ptr) (i32.const {offset}))");
+                        result.push(visit(self, field, addr)?);
+                    }
+                }
+                join!(result)
+            }
+            Type::Enum(variants) => {
+                let variants = variants.values().cloned().enumerate().collect();
+                self.tagged(variants, &visit)?
+            }
+            // Closure has the values that its lambda captured, which is known by the table index
+            typ @ Type::Function(..) => {
+                let index = self.layout(&typ)?;
+                let mut lambdas = vec![];
+                for (tag, (lambda, captured)) in self.table.clone().values().enumerate() {
+                    if self.layout(lambda)? == index {
+                        lambdas.push((tag, captured.values().cloned().collect()));
+                    }
+                }
+                self.tagged(lambdas, &visit)?
+            }
+            Type::Array(element) if is_ptr!(element, self) => {
+                let addr = format!(
+                    "(i32.add (local.get $ptr) (i32.mul (i32.add (local.get $index) (i32.const 1)) (i32.const {BYTES})))"
+                );
+                format!(
+                    concat!(
+                        "(block $done (loop $each (br_if $done (i32.ge_u (local.get $index) (i32.load (local.get $ptr)))) ",
+                        "{visit} (local.set $index (i32.add (local.get $index) (i32.const 1))) (br $each)))"
+                    ),
+                    visit = visit(self, &element, addr)?
+                )
+            }
+            _ => String::new(),
+        })
+    }
+
+    /// Code that visits the pointer fields of the object, which depend on the tag in its first slot
+    fn tagged(
+        &mut self,
+        variants: Vec<(usize, Vec<Type>)>,
+        visit: &impl Fn(&mut Compiler, &Type, String) -> Option<String>,
+    ) -> Option<String> {
+        let mut result = vec![];
+        for (tag, fields) in variants {
+            let mut visits = vec![];
+            for (index, field) in fields.iter().enumerate() {
+                if is_ptr!(field, self) {
+                    let offset = (index as i32 + 1) * BYTES;
+                    let addr = format!("(i32.add (local.get $ptr) (i32.const {offset}))");
+                    visits.push(visit(self, field, addr)?);
+                }
+            }
+            if !visits.is_empty() {
+                result.push(format!(
+                    "(if (i32.eq (i32.load (local.get $ptr)) (i32.const {tag})) (then {}))",
+                    join!(visits)
+                ));
+            }
+        }
+        Some(join!(result))
+    }
+}
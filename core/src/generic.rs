@@ -0,0 +1,239 @@
+use crate::*;
+
+impl Compiler {
+    /// Run the check or compilation where the type parameters stand for the types
+    pub(crate) fn with_params<T>(
+        &mut self,
+        params: &[String],
+        types: Vec<Type>,
+        run: impl FnOnce(&mut Self) -> Option<T>,
+    ) -> Option<T> {
+        let bound = params.iter().cloned().zip(types).collect();
+        let outer = std::mem::replace(&mut self.params, bound);
+        let result = run(self);
+        self.params = outer;
+        result
+    }
+
+    /// Type arguments of the generic function that are inferred from the call,
+    /// checking the arguments with them
+    pub(crate) fn type_args(&mut self, name: &str, args: &[Expr]) -> Option<Dict> {
+        let (params, _) = self.template.get(name)?.clone();
+        let function = self.function.get(name)?.clone();
+        arglen_check!(name, args, function.arguments, "function", self);
+        let mut bound = IndexMap::new();
+        for (arg, typ) in args.iter().zip(function.arguments.values()) {
+            self.span = arg.span();
+            let arg = arg.infer(self)?.infer(self)?;
+            unify(typ, &arg, &params, &mut bound);
+        }
+        let mut result = IndexMap::new();
+        for param in &params {
+            let Some(typ) = bound.get(param) else {
+                self.report(format!(
+                    "can't infer type parameter `{param}` of `{name}` from the arguments"
+                ));
+                return None;
+            };
+            result.insert(param.clone(), typ.clone());
+        }
+        for (arg, typ) in args.iter().zip(function.arguments.values()) {
+            self.span = arg.span();
+            self.expect_value(&substitute(typ, &result), arg)?;
+        }
+        Some(result)
+    }
+
+    /// Return type of the generic function called with the arguments
+    pub(crate) fn instance_type(&mut self, name: &str, args: &[Expr]) -> Option<Type> {
+        let bound = self.type_args(name, args)?;
+        let (params, definition) = self.template.get(name)?.clone();
+        let function = self.function.get(name)?.clone();
+        // Function whose body is only checked at each call returns the type found by the check
+        match &function.returns {
+            Type::Var(var) if !params.contains(var) => {}
+            returns => return Some(substitute(returns, &bound)),
+        }
+        let stmt = specialize(&definition, name, &function.arguments, &bound)?;
+        // Definition is hidden in the check, so that its recursive call isn't checked again
+        self.template.swap_remove(name);
+        let returns = self.isolated(&params, bound, |ctx| {
+            stmt.infer(ctx)?;
+            Some(ctx.function.get(name)?.returns.clone())
+        });
+        self.function.insert(name.to_string(), function);
+        self.template.insert(name.to_string(), (params, definition));
+        returns
+    }
+
+    /// Function of the generic one for the type arguments, that's generated at the first call
+    pub(crate) fn instance(&mut self, name: &str, args: &[Expr]) -> Option<String> {
+        let bound = self.type_args(name, args)?;
+        let (params, definition) = self.template.get(name)?.clone();
+        // Recursive alias is found unrolled or not, so it's keyed by the alias name
+        let types = bound.values().map(|typ| typ.restore_alias(self).format());
+        let types: Vec<_> = types.collect();
+        let key = format!("{name}<{}>", types.join(", "));
+        if let Some(index) = self.instances.get_index_of(&key) {
+            return Some(format!("{name}.{index}"));
+        }
+        // Registered before the body, as it may call itself with the same types
+        let (index, _) = self.instances.insert_full(key);
+        let instance = format!("{name}.{index}");
+        let arguments = &self.function.get(name)?.arguments;
+        let stmt = specialize(&definition, &instance, arguments, &bound)?;
+        self.isolated(&params, bound, |ctx| {
+            stmt.infer(ctx)?;
+            stmt.compile(ctx)
+        })?;
+        Some(instance)
+    }
+
+    /// Run the check or compilation of the function for the type arguments
+    fn isolated<T>(
+        &mut self,
+        params: &[String],
+        bound: Dict,
+        run: impl FnOnce(&mut Self) -> Option<T>,
+    ) -> Option<T> {
+        // Instance is a function on its own, even if it's first called in a lambda or a loop
+        let outer = std::mem::take(&mut self.outer);
+        let captured = std::mem::take(&mut self.captured);
+        let narrowed = std::mem::take(&mut self.narrowed);
+        let in_while = std::mem::replace(&mut self.in_while, false);
+        let result = self.with_params(params, bound.into_values().collect(), run);
+        self.outer = outer;
+        self.captured = captured;
+        self.narrowed = narrowed;
+        self.in_while = in_while;
+        result
+    }
+
+    /// Generic alias given the type arguments, that the type is the definition of
+    pub(crate) fn instance_of(&self, typ: &Type) -> Option<Type> {
+        self.generic.iter().find_map(|(name, params)| {
+            let body = self.alias.get(name)?;
+            let mut bound = IndexMap::new();
+            unify(body, typ, params, &mut bound);
+            let args = params.iter().map(|param| bound.get(param).cloned());
+            let args = args.collect::<Option<Vec<_>>>()?;
+            // Definition itself has its parameters in place of the arguments
+            if args
+                .iter()
+                .any(|arg| matches!(arg, Type::Alias(arg) if params.contains(arg)))
+            {
+                return None;
+            }
+            (substitute(body, &bound) == *typ).then_some(Type::Generic(name.clone(), args))
+        })
+    }
+}
+
+/// Bind the type parameters in the argument type to the parts of the given type
+fn unify(param: &Type, given: &Type, params: &[String], bound: &mut Dict) {
+    match (param, given) {
+        (Type::Param(name) | Type::Alias(name) | Type::Var(name), _) if params.contains(name) => {
+            bound.entry(name.clone()).or_insert(given.clone());
+        }
+        (Type::Nullable(param), Type::Nullable(given)) => unify(param, given, params, bound),
+        (Type::Nullable(param), given) => unify(param, given, params, bound),
+        (Type::Array(param), Type::Array(given)) => unify(param, given, params, bound),
+        (Type::Dict(param), Type::Dict(given)) => {
+            for (param, given) in param.values().zip(given.values()) {
+                unify(param, given, params, bound);
+            }
+        }
+        (Type::Enum(param), Type::Enum(given)) => {
+            let [param, given] = [param, given].map(|typ| typ.values().flatten());
+            for (param, given) in param.zip(given) {
+                unify(param, given, params, bound);
+            }
+        }
+        (Type::Function(param, ret), Type::Function(given, given_ret)) => {
+            for (param, given) in param.iter().zip(given) {
+                unify(param, given, params, bound);
+            }
+            unify(ret, given_ret, params, bound);
+        }
+        (Type::Generic(name, param), Type::Generic(given_name, given)) if name == given_name => {
+            for (param, given) in param.iter().zip(given) {
+                unify(param, given, params, bound);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Type where the type parameters are replaced with the bound types
+pub(crate) fn substitute(typ: &Type, bound: &Dict) -> Type {
+    let each = |types: &[Type]| types.iter().map(|typ| substitute(typ, bound)).collect();
+    match typ {
+        Type::Param(name) | Type::Alias(name) | Type::Var(name) if bound.contains_key(name) => {
+            bound[name].clone()
+        }
+        Type::Nullable(typ) => substitute(typ, bound).nullable(),
+        Type::Array(typ) => Type::Array(Box::new(substitute(typ, bound))),
+        Type::Dict(dict) => Type::Dict(
+            dict.iter()
+                .map(|(key, typ)| (key.clone(), substitute(typ, bound)))
+                .collect(),
+        ),
+        Type::Enum(variants) => Type::Enum(
+            variants
+                .iter()
+                .map(|(key, fields)| (key.clone(), each(fields)))
+                .collect(),
+        ),
+        Type::Function(args, ret) => Type::Function(each(args), Box::new(substitute(ret, bound))),
+        Type::Generic(name, args) => Type::Generic(name.clone(), each(args)),
+        typ => typ.clone(),
+    }
+}
+
+/// Name of the function that the definition `name(args)` or `name(args): T` defines
+pub(crate) fn function_name(target: &Expr) -> Option<&String> {
+    match target {
+        Expr::Call(name, _, _) => Some(name),
+        Expr::Operator(oper, _) => match oper.as_ref() {
+            Op::Cast(call, _) => function_name(call),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Definition of the generic function for the type arguments, with the other name
+fn specialize(definition: &Stmt, name: &str, arguments: &Dict, bound: &Dict) -> Option<Stmt> {
+    let Stmt::Let(scope, target, value, span) = definition else {
+        return None;
+    };
+    let arguments = arguments.iter();
+    let arguments = arguments.map(|(arg, typ)| (arg.clone(), substitute(typ, bound)));
+    let target = rename(target, name, &arguments.collect())?;
+    Some(Stmt::Let(*scope, target, value.clone(), *span))
+}
+
+/// Function definition with the other name, annotating the arguments that don't have one
+fn rename(target: &Expr, name: &str, arguments: &Dict) -> Option<Expr> {
+    match target {
+        Expr::Call(_, args, span) => {
+            let args = args.iter().map(|arg| match arg {
+                Expr::Variable(arg_name, span) => {
+                    let cast = Op::Cast(arg.clone(), arguments.get(arg_name)?.clone());
+                    Some(Expr::Operator(Box::new(cast), *span))
+                }
+                arg => Some(arg.clone()),
+            });
+            let args = args.collect::<Option<_>>()?;
+            Some(Expr::Call(name.to_string(), args, *span))
+        }
+        Expr::Operator(oper, span) => {
+            let Op::Cast(call, ret) = oper.as_ref() else {
+                return None;
+            };
+            let oper = Op::Cast(rename(call, name, arguments)?, ret.clone());
+            Some(Expr::Operator(Box::new(oper), *span))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,235 @@
+use crate::{
+    generic::{function_name, substitute},
+    object::is_generic,
+    *,
+};
+
+impl Compiler {
+    /// Check the function whose arguments without annotation are given the type variables,
+    /// that the body binds, and the unbound ones become the type parameters instead
+    pub(crate) fn implicit(
+        &mut self,
+        stmt: &Stmt,
+        args: &[Expr],
+        check: impl FnOnce(&mut Self, &[Expr]) -> Option<Function>,
+    ) -> Option<Function> {
+        // Variables are fresh, so that they don't meet the ones of the other functions
+        let (args, vars) = annotate(args, self.count);
+        if vars.is_empty() {
+            return check(self, &args);
+        }
+        let first = self.count;
+        self.count += vars.len() + 1;
+        let Stmt::Let(scope, target, _, _) = stmt else {
+            return None;
+        };
+        let name = function_name(target)?.clone();
+        let outer = self.params.clone();
+        let errors = self.errors.len();
+        let frame = check(self, &args);
+        let bound = solved(std::mem::replace(&mut self.params, outer));
+        let unbound: Vec<_> = vars
+            .iter()
+            .filter(|(var, _, _)| !bound.contains_key(var))
+            .collect();
+        let frame = match frame {
+            Some(frame) => frame,
+            None if unbound.is_empty() => return None,
+            // Body that needs the argument types is checked at each call instead
+            None if matches!(scope, Scope::Local) => {
+                self.errors.truncate(errors);
+                let returns = match target {
+                    Expr::Operator(oper, _) => match oper.as_ref() {
+                        Op::Cast(_, ret) => ret.clone(),
+                        _ => return None,
+                    },
+                    _ => Type::Var(type_var(first + vars.len())),
+                };
+                let arguments = self.argument.clone();
+                Function {
+                    variables: IndexMap::new(),
+                    arguments,
+                    returns,
+                }
+            }
+            None => {
+                // Errors of the body are about the variables, which the annotation would resolve
+                self.errors.truncate(errors);
+                let (_, arg, span) = unbound[0];
+                self.span = *span;
+                self.report(format!(
+                    "function argument `{arg}` needs type annotation, as its type isn't inferred from the body"
+                ));
+                return None;
+            }
+        };
+        let frame = Function {
+            variables: each(&frame.variables, &bound),
+            arguments: each(&frame.arguments, &bound),
+            returns: substitute(&frame.returns, &bound),
+        };
+        // Definition of the generic function has its own type parameters too
+        let mut params: Vec<_> = self
+            .params
+            .iter()
+            .filter(|(_, typ)| matches!(typ, Type::Param(_)))
+            .map(|(param, _)| param.clone())
+            .collect();
+        if unbound.is_empty() {
+            if params.is_empty() {
+                self.template.swap_remove(&name);
+            }
+            return Some(frame);
+        }
+        if let (Scope::Global, Some((_, arg, _))) = (scope, unbound.first()) {
+            self.report(format!(
+                "can't export function `{name}`, as any type can be given to argument `{arg}`"
+            ));
+            return None;
+        }
+        // Type parameters are named in order, as the bound variables are gone
+        let mut free: Vec<_> = unbound.iter().map(|(var, _, _)| var.clone()).collect();
+        free.push(type_var(first + vars.len()));
+        let renamed: Dict = free
+            .into_iter()
+            .enumerate()
+            .map(|(index, var)| (var, Type::Var(type_var(index))))
+            .collect();
+        let frame = Function {
+            variables: each(&frame.variables, &renamed),
+            arguments: each(&frame.arguments, &renamed),
+            returns: substitute(&frame.returns, &renamed),
+        };
+        params.extend((0..unbound.len()).map(type_var));
+        self.template.insert(name, (params, stmt.clone()));
+        Some(frame)
+    }
+
+    /// Infer the body of the function, whose recursive calls return a type variable
+    /// that the body binds where the result is used
+    pub(crate) fn recursive(&mut self, name: &str, body: &Expr) -> Option<Function> {
+        let var = type_var(self.count);
+        self.count += 1;
+        let function = Function {
+            variables: self.variable.clone(),
+            arguments: self.argument.clone(),
+            returns: Type::Var(var.clone()),
+        };
+        let outer = self.function.insert(name.to_string(), function);
+        let returns = body.infer(self);
+        match outer {
+            Some(function) => self.function.insert(name.to_string(), function),
+            None => self.function.swap_remove(name),
+        };
+        let returns = returns?;
+        type_check!(Type::Var(var.clone()), returns, self)?;
+        let Some(typ) = self.params.swap_remove(&var) else {
+            self.report(format!(
+                "function `{name}` needs return type annotation, as it only returns its own result"
+            ));
+            return None;
+        };
+        let bound = IndexMap::from([(var, typ)]);
+        Some(Function {
+            variables: each(&self.variable, &bound),
+            arguments: self.argument.clone(),
+            returns: substitute(&returns, &bound),
+        })
+    }
+
+    /// Return type of the call checking the arguments, where `any` of the signature is
+    /// a type variable of its own, so that each call can give a different type to it
+    pub(crate) fn call_type(&mut self, function: &Function, args: &[Expr]) -> Option<Type> {
+        let types = function.arguments.values();
+        if !types.clone().chain([&function.returns]).any(is_generic) {
+            for (arg, typ) in args.iter().zip(types) {
+                self.span = arg.span();
+                self.expect_value(typ, arg)?;
+            }
+            return Some(function.returns.clone());
+        }
+        let var = type_var(self.count);
+        self.count += 1;
+        for (arg, typ) in args.iter().zip(types) {
+            self.span = arg.span();
+            self.expect_value(&typ.instantiate(&Type::Var(var.clone())), arg)?;
+        }
+        // Variable that no argument binds is left as `any`
+        let bound = self.params.entry(var).or_insert(Type::Any).clone();
+        Some(function.returns.instantiate(&bound))
+    }
+
+    /// Bind the type variable to the type, or compare the bound one with it
+    pub(crate) fn bind_var(&mut self, name: &str, typ: &Type) -> bool {
+        if let Some(bound) = self.params.get(name).cloned() {
+            return bound.compare(typ, self);
+        }
+        // Variable is bound to what the other one is bound to, through a chain that can't loop
+        let mut typ = typ.clone();
+        let mut seen = vec![];
+        while let Type::Var(other) = &typ {
+            if other == name {
+                return true;
+            }
+            let Some(bound) = self.params.get(other).filter(|_| !seen.contains(other)) else {
+                break;
+            };
+            seen.push(other.clone());
+            typ = bound.clone();
+        }
+        // Type that includes the variable itself would be infinite
+        let itself = IndexMap::from([(name.to_string(), Type::Void)]);
+        if substitute(&typ, &itself) != typ {
+            return false;
+        }
+        self.params.insert(name.to_string(), typ);
+        true
+    }
+}
+
+/// Arguments where the ones without annotation are given the type variables from the index,
+/// with the variable, name and span of each
+fn annotate(args: &[Expr], first: usize) -> (Vec<Expr>, Vec<(String, String, Span)>) {
+    let mut vars = vec![];
+    let args = args
+        .iter()
+        .map(|arg| {
+            let Expr::Variable(name, span) = arg else {
+                return arg.clone();
+            };
+            let var = type_var(first + vars.len());
+            vars.push((var.clone(), name.clone(), *span));
+            let cast = Op::Cast(arg.clone(), Type::Var(var));
+            Expr::Operator(Box::new(cast), *span)
+        })
+        .collect();
+    (args, vars)
+}
+
+/// Type variable named `'a`, `'b`, ... by the index
+fn type_var(index: usize) -> String {
+    let letter = char::from(b'a' + (index % 26) as u8);
+    match index / 26 {
+        0 => format!("'{letter}"),
+        count => format!("'{letter}{count}"),
+    }
+}
+
+/// Types where the type variables are replaced with the bound types
+fn each(types: &Dict, bound: &Dict) -> Dict {
+    let types = types.iter();
+    types
+        .map(|(key, typ)| (key.clone(), substitute(typ, bound)))
+        .collect()
+}
+
+/// Bindings of the type variables, resolved through the other variables
+fn solved(mut bound: Dict) -> Dict {
+    for _ in 0..bound.len() {
+        let outer = bound.clone();
+        for typ in bound.values_mut() {
+            *typ = substitute(typ, &outer);
+        }
+    }
+    bound
+}
@@ -1,115 +1,165 @@
 use crate::*;
+use std::num::IntErrorKind;
 
-pub fn tokenize(
-    input: &str,
-    delimiter: &[&str],
-    is_expr: bool,
-    is_trim: bool,
-    is_split: bool,
-) -> Option<Vec<String>> {
-    let mut tokens: Vec<String> = Vec::new();
-    let mut current_token = String::new();
-    let mut in_parentheses: usize = 0;
-    let mut in_quote = false;
-    let mut is_escape = false;
-    let mut is_comment = false;
-
-    let chars: Vec<String> = input.chars().map(String::from).collect();
-    let mut index = 0;
+/// Lexical unit of the source code
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Keyword(&'static str),
+    Int(u64),
+    Float(f64),
+    /// Integer literal with the suffix `L`
+    Long(u64),
+    /// Number literal with the suffix `D`
+    Double(f64),
+    /// Integer literal with the suffix `U`
+    Unsigned(u64),
+    Str(String),
+    /// Formatted string `f"..."`
+    FStr(String),
+    Operator(&'static str),
+    Punct(char),
+    /// Comment `~~ ... ~~` with its delimiters, which only the formatter keeps
+    Comment(String),
+}
 
-    fn include_letter(query: &str, chars: &Vec<String>, idx: usize) -> bool {
-        chars
-            .clone()
-            .get(idx..idx + query.chars().count())
-            .map(|i| query == i.concat())
-            .unwrap_or(false)
+impl Token {
+    /// Description used in error messages
+    pub fn format(&self) -> String {
+        match self {
+            Token::Ident(name) => format!("identifier `{name}`"),
+            Token::Keyword(word) => format!("keyword `{word}`"),
+            Token::Int(n) => format!("integer `{n}`"),
+            Token::Float(n) => format!("number `{n}`"),
+            Token::Long(n) => format!("long `{n}L`"),
+            Token::Double(n) => format!("double `{n}D`"),
+            Token::Unsigned(n) => format!("unsigned `{n}U`"),
+            Token::Str(_) => "string literal".to_string(),
+            Token::FStr(_) => "formatted string".to_string(),
+            Token::Operator(op) => format!("`{op}`"),
+            Token::Punct(c) => format!("`{c}`"),
+            Token::Comment(_) => "comment".to_string(),
+        }
     }
+}
 
-    while index < chars.len() {
-        let c = chars.get(index)?.to_owned();
-        if include_letter("~~", &chars, index) && !in_quote {
-            is_comment = !is_comment;
-            index += 2;
-            continue;
-        }
-        if is_comment {
-            index += 1;
+/// Split the source code into tokens with their spans
+pub fn tokenize(source: &str, offset: usize) -> Result<Vec<(Token, Span)>, Diagnostic> {
+    let mut tokens = vec![];
+    let mut index = 0;
+    while let Some(c) = source[index..].chars().next() {
+        let (rest, start) = (&source[index..], index);
+        let span = |len: usize| Span::new(offset + start, offset + start + len);
+        let (token, len) = if c.is_whitespace() || SPACE.contains(&c.to_string().as_str()) {
+            index += c.len_utf8();
             continue;
-        }
-        if is_escape {
-            current_token.push_str(&c);
-            is_escape = false;
-            index += 1;
-        } else if ["(", "[", "{"].contains(&c.as_str()) && !in_quote {
-            if is_split && in_parentheses == 0 {
-                tokens.push(current_token.clone());
-                current_token.clear();
-            }
-            current_token.push_str(c.as_str());
-            in_parentheses += 1;
-            index += 1;
-        } else if [")", "]", "}"].contains(&c.as_str()) && !in_quote {
-            current_token.push_str(c.as_str());
-            in_parentheses = in_parentheses.checked_sub(1)?;
-            index += 1;
-        } else if c == "\"" {
-            in_quote = !in_quote;
-            current_token.push_str(c.as_str());
-            index += 1;
-        } else if c == "\\" {
-            current_token.push_str(&c);
-            is_escape = true;
-            index += 1;
-        } else {
-            let mut is_opr = false;
-            if is_expr {
-                for op in OPERATOR {
-                    if include_letter(op, &chars, index) && in_parentheses == 0 && !in_quote {
-                        if current_token.is_empty() {
-                            index += op.chars().count();
-                            tokens.push(op.to_string());
-                        } else {
-                            tokens.push(current_token.to_string());
-                            index += op.chars().count();
-                            tokens.push(op.to_string());
-                            current_token.clear();
-                        }
-                        is_opr = true;
-                        break;
-                    }
+        // Comment `~~ ... ~~`
+        } else if let Some(comment) = rest.strip_prefix("~~") {
+            let Some(len) = comment.find("~~").map(|i| i + 4) else {
+                return Err(Diagnostic::error("unterminated comment", span(2)));
+            };
+            (Token::Comment(rest[..len].to_string()), len)
+        // Number literal, where `.` must be followed by digits not to clash with field access
+        } else if c.is_ascii_digit() {
+            // Prefix `0x` or `0b` is for hexadecimal or binary integers
+            let (radix, prefix) = match rest.get(..2) {
+                Some("0x") => (16, 2),
+                Some("0b") => (2, 2),
+                _ => (10, 0),
+            };
+            // Digits may be separated by `_` like `1_000_000`
+            let digits = |s: &str, radix| {
+                s.find(|c: char| !c.is_digit(radix) && c != '_')
+                    .unwrap_or(s.len())
+            };
+            let int = prefix + digits(&rest[prefix..], radix);
+            let frac = rest[int..]
+                .strip_prefix('.')
+                .filter(|_| radix == 10)
+                .map(|frac| digits(frac, 10))
+                .filter(|&len| len != 0);
+            let len = frac.map_or(int, |frac| int + 1 + frac);
+            // Suffix `L` makes it `long`, `D` makes it `double` and `U` makes it `uint`,
+            // unless it begins a word
+            let mut suffix = rest[len..].chars();
+            let suffix = suffix
+                .next()
+                .filter(|c| ['L', 'D', 'U'].contains(c))
+                .filter(|&c| radix == 10 || c != 'D')
+                .filter(|_| !suffix.next().is_some_and(UnicodeXID::is_xid_continue));
+            let text = rest[prefix..len].replace('_', "");
+            let token = match (suffix, frac) {
+                (Some('D'), _) => Token::Double(text.parse().unwrap_or_default()),
+                (Some(_), Some(_)) => {
+                    let message = "integer literal can't have a fractional part";
+                    return Err(Diagnostic::error(message, span(len + 1)));
                 }
-            }
-            let mut is_delimit = false;
-            if !is_opr {
-                for delimit in delimiter {
-                    if include_letter(delimit, &chars, index) && in_parentheses == 0 && !in_quote {
-                        if current_token.is_empty() {
-                            index += delimit.chars().count();
-                        } else {
-                            tokens.push(current_token.clone());
-                            index += delimit.chars().count();
-                            current_token.clear();
+                (None, Some(_)) => Token::Float(text.parse().unwrap_or_default()),
+                (_, None) => {
+                    let n = match u64::from_str_radix(&text, radix) {
+                        Ok(n) => n,
+                        Err(err) if *err.kind() == IntErrorKind::Empty => {
+                            let message = "integer literal has no digits";
+                            return Err(Diagnostic::error(message, span(len)));
+                        }
+                        Err(_) => {
+                            let message = "integer literal is too large";
+                            return Err(Diagnostic::error(message, span(len)));
                         }
-                        is_delimit = true;
-                        break;
+                    };
+                    match suffix {
+                        Some('L') => Token::Long(n),
+                        Some('U') => Token::Unsigned(n),
+                        _ => Token::Int(n),
                     }
                 }
-                if !is_delimit {
-                    current_token.push_str(c.as_str());
-                    index += 1;
-                }
+            };
+            (token, len + suffix.map_or(0, char::len_utf8))
+        // String literal `"..."` and formatted string `f"..."`
+        } else if c == '"' || rest.starts_with("f\"") {
+            let head = rest.find('"').unwrap_or_default() + 1;
+            let mut is_escape = false;
+            let body = rest[head..].find(|c| {
+                let is_end = c == '"' && !is_escape;
+                is_escape = c == '\\' && !is_escape;
+                is_end
+            });
+            let Some(body) = body else {
+                return Err(Diagnostic::error("unterminated string literal", span(head)));
+            };
+            let text = rest[head..head + body].to_string();
+            let token = if head == 1 {
+                Token::Str(text)
+            } else {
+                Token::FStr(text)
+            };
+            (token, head + body + 1)
+        } else if UnicodeXID::is_xid_start(c) || c == '_' {
+            let len = rest
+                .find(|c| !UnicodeXID::is_xid_continue(c))
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            if !word.is_ascii() {
+                let message = format!("identifier `{word}` contains non-ASCII characters");
+                return Err(Diagnostic::error(message, span(len)));
             }
-        }
-    }
-
-    // Syntax error check
-    if is_escape || in_quote || in_parentheses != 0 {
-        return None;
-    }
-    if !is_trim || (is_trim && !current_token.is_empty()) {
-        tokens.push(current_token.clone());
+            if let Some(keyword) = RESERVED.iter().find(|&&i| i == word) {
+                (Token::Keyword(keyword), len)
+            } else {
+                (Token::Ident(word.to_string()), len)
+            }
+        } else if let Some(op) = OPERATOR.iter().find(|&&op| rest.starts_with(op)) {
+            (Token::Operator(op), op.len())
+        } else if "()[]{},;.@#".contains(c) {
+            (Token::Punct(c), 1)
+        } else {
+            let message = format!("unexpected character `{c}`");
+            return Err(Diagnostic::error(message, span(c.len_utf8())));
+        };
+        tokens.push((token, span(len)));
+        index += len;
     }
-    Some(tokens)
+    Ok(tokens)
 }
 
 pub fn str_format(input: &str) -> Option<Vec<String>> {
@@ -164,25 +214,3 @@ pub fn str_format(input: &str) -> Option<Vec<String>> {
     }
     Some(tokens)
 }
-
-pub fn is_identifier(name: &mut String) -> bool {
-    *name = name.trim().to_owned();
-    if name.is_empty() {
-        return false;
-    }
-    let mut chars = name.chars();
-    let first_char = chars.next().unwrap();
-    if !UnicodeXID::is_xid_start(first_char) {
-        return false;
-    }
-    if !chars.all(UnicodeXID::is_xid_continue) {
-        return false;
-    }
-    if RESERVED.contains(&name.as_str()) {
-        return false;
-    }
-    if !name.is_ascii() {
-        return false;
-    }
-    true
-}
@@ -1,30 +1,50 @@
 mod block;
+mod check;
+mod closure;
+mod diagnostic;
 mod expr;
+mod formatter;
+mod gc;
+mod generic;
+mod implicit;
 mod lexer;
+mod memory;
+mod null;
+mod object;
 mod op;
+mod parser;
 mod stmt;
+mod summary;
 mod r#type;
 mod utils;
 mod value;
+mod variant;
 
 use indexmap::{IndexMap, IndexSet};
 use unicode_xid::UnicodeXID;
 
 pub use {
     block::Block,
+    check::{Check, Trap},
+    diagnostic::{Diagnostic, Severity, Span},
     expr::Expr,
-    lexer::{is_identifier, str_format, tokenize},
-    op::Op,
-    stmt::Stmt,
+    formatter::format,
+    gc::Gc,
+    lexer::{Token, str_format, tokenize},
+    memory::PAGE_SIZE,
+    op::{Assoc, Op},
+    parser::Parser,
+    stmt::{Scope, Stmt},
+    summary::{FunctionSummary, OverloadSummary, Summary},
     r#type::{Dict, Enum, Type},
-    utils::{BYTES, OPERATOR, RESERVED, SPACE},
+    utils::{BUILTIN, BYTES, OPERATOR, RESERVED, SPACE, WORD},
     value::Value,
 };
 
 pub trait Node {
     fn compile(&self, ctx: &mut Compiler) -> Option<String>;
     fn infer(&self, ctx: &mut Compiler) -> Option<Type>;
-    fn parse(source: &str) -> Option<Self>
+    fn parse(parser: &mut Parser) -> Option<Self>
     where
         Self: Node + Sized;
 }
@@ -58,8 +78,20 @@ pub struct Compiler {
     pub overload: IndexMap<String, (usize, (Type, Type))>,
     /// Type alias that's defined by user
     pub alias: IndexMap<String, Type>,
+    /// Type parameters of the generic type aliases
+    pub generic: IndexMap<String, Vec<String>>,
+    /// Types that the type parameters stand for, in the generic definition that's processing
+    pub params: Dict,
+    /// Generic functions with the type parameters and the definitions
+    pub template: IndexMap<String, (Vec<String>, Stmt)>,
+    /// Generic function calls with the type arguments, whose code has been generated
+    pub instances: IndexSet<String>,
     /// Errors that occurred during compilation
-    pub error: Option<String>,
+    pub errors: Vec<Diagnostic>,
+    /// Names whose definition failed, not to report errors of their uses
+    pub poisoned: IndexSet<String>,
+    /// Source span of the node that's processing
+    pub span: Span,
     /// Flag to indicate if we are inside a while loop
     pub in_while: bool,
     /// Type environment for variable
@@ -74,6 +106,30 @@ pub struct Compiler {
     pub export: IndexMap<String, Function>,
     /// Type of main program returns
     pub result: Type,
+    /// Page count of the linear memory at the start
+    pub initial_pages: u32,
+    /// Page count that the memory can grow up to, or unlimited
+    pub max_pages: Option<u32>,
+    /// Strategy to reclaim heap objects
+    pub gc: Gc,
+    /// Pointer types that need functions to manage their memory
+    pub layouts: IndexMap<String, Type>,
+    /// Runtime checks to emit
+    pub checks: IndexSet<Check>,
+    /// Functions that closures call indirectly, with their types and the captured variables
+    pub table: IndexMap<String, (Type, Dict)>,
+    /// Variables of the enclosing functions that the lambda can capture
+    pub outer: IndexMap<String, Type>,
+    /// Variables that the lambda captured, in the order of its environment
+    pub captured: IndexMap<String, Type>,
+    /// Fields like `a.n` that the condition checked not to be null, with the types
+    pub narrowed: Dict,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new()
+    }
 }
 
 impl Compiler {
@@ -83,45 +139,104 @@ impl Compiler {
             allocator: 0,
             import: vec![],
             data: vec![],
-            error: None,
+            errors: vec![],
+            poisoned: IndexSet::new(),
+            span: Span::default(),
             in_while: false,
             declare: IndexMap::new(),
             module: IndexMap::new(),
             overload: IndexMap::new(),
             alias: IndexMap::new(),
+            generic: IndexMap::new(),
+            params: IndexMap::new(),
+            template: IndexMap::new(),
+            instances: IndexSet::new(),
             variable: IndexMap::new(),
             global: IndexMap::new(),
             argument: IndexMap::new(),
             function: IndexMap::new(),
             export: IndexMap::new(),
             result: Type::Void,
+            initial_pages: 64,
+            max_pages: None,
+            gc: Gc::None,
+            layouts: IndexMap::new(),
+            checks: IndexSet::new(),
+            table: IndexMap::new(),
+            outer: IndexMap::new(),
+            captured: IndexMap::new(),
+            narrowed: IndexMap::new(),
         }
     }
 
+    /// Parse the source code, reporting syntax errors
+    pub fn parse(&mut self, source: &str) -> Option<Block> {
+        Parser::parse::<Block>(source, 0)
+            .map_err(|errors| self.errors.extend(errors))
+            .ok()
+    }
+
+    /// Type check the program without compiling it
+    pub fn check(&mut self, ast: &Block) -> Option<()> {
+        let result = ast.infer(self);
+        let Some(result) = result.filter(|_| self.errors.is_empty()) else {
+            self.fallback("failed to type check");
+            return None;
+        };
+        self.result = result;
+        Some(())
+    }
+
     pub fn build(&mut self, source: &str) -> Option<String> {
-        let ast = Block::parse(source)?;
-        self.result = ast.infer(self)?;
+        let ast = self.parse(source)?;
+        self.compile(&ast)
+    }
+
+    /// Type check and compile the program into a module
+    pub fn compile(&mut self, ast: &Block) -> Option<String> {
+        self.check(ast)?;
+        let Some(code) = ast.compile(self) else {
+            self.fallback("failed to compile");
+            return None;
+        };
+        let result = self.result.clone();
+        let code = format!("{} {}", self.prologue()?, self.epilogue(&result, code)?);
+        let main = format!(
+            "(func (export \"_start\") {ret} {locals} {temp} {code})",
+            locals = expand_local!(self),
+            temp = self.epilogue_local(&result)?,
+            ret = compile_return!(result, self),
+        );
         Some(format!(
-            "(module {import} {memory} {memcpy} {strings} {declare} {global} {main})",
-            main = format!(
-                "(func (export \"_start\") {ret} {locals} {code})",
-                code = ast.compile(self)?,
-                locals = expand_local!(self),
-                ret = compile_return!(self.result.clone(), self),
-            ),
+            "(module {import} {memory} {checks} {gc} {strings} {declare} {global} {main})",
+            // Types of globals may be new objects, so they're compiled before the definitions
+            global = expand_global!(self),
+            gc = self.expand_gc()?,
             import = join!(self.import),
             strings = join!(self.data),
-            declare = join!(self.declare.values().cloned().collect::<Vec<String>>()),
-            global = expand_global!(self),
-            memory = "(memory $mem (export \"mem\") 64)",
-            memcpy = &format!(
-                "(global $allocator (export \"allocator\") (mut i32) (i32.const {allocator})) {}",
-                format!(
-                    "(func $malloc (export \"malloc\") (param $size i32) (result i32) (global.get $allocator) {}",
-                    "(global.set $allocator (i32.add (global.get $allocator) (local.get $size))))"
-                ),
-                allocator = self.allocator
-            ),
+            declare = join!(self.expand_declare()),
+            memory = self.expand_memory(),
+            checks = self.expand_checks(),
         ))
     }
+
+    /// Report an error at the span of the node that's processing
+    pub fn report(&mut self, message: impl ToString) {
+        self.errors.push(Diagnostic::error(message, self.span));
+    }
+
+    /// Run a check whose failure is tolerated, discarding errors it reported
+    pub fn tolerate<T>(&mut self, check: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        let errors = self.errors.len();
+        let result = check(self);
+        self.errors.truncate(errors);
+        result
+    }
+
+    /// Report a general error if no specific error has been reported
+    fn fallback(&mut self, message: &str) {
+        if self.errors.is_empty() {
+            self.report(message);
+        }
+    }
 }
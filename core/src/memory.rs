@@ -0,0 +1,85 @@
+use crate::{
+    gc::{SHADOW_STACK, WORKLIST, static_marks},
+    *,
+};
+
+/// Byte size of a page of the linear memory
+pub const PAGE_SIZE: i32 = 65536;
+/// Count of the size classes, where the class `n` is for blocks of `2^n` bytes
+const SIZE_CLASSES: i32 = 32;
+
+impl Compiler {
+    /// Addresses of the free list heads, the shadow stack and the heap, placed after the static data,
+    /// where the worklist and the marks of the static objects follow the shadow stack
+    pub(crate) fn regions(&self) -> (i32, i32, i32) {
+        let table = (self.allocator + BYTES - 1) / BYTES * BYTES;
+        let stack = table + SIZE_CLASSES * WORD;
+        let heap = match self.gc {
+            Gc::MarkSweep => stack + SHADOW_STACK + WORKLIST + static_marks(table),
+            _ => stack,
+        };
+        (table, stack, heap)
+    }
+
+    /// Linear memory, and the allocator that reuses freed blocks and grows it when it runs out
+    pub(crate) fn expand_memory(&self) -> String {
+        let (table, _, heap) = self.regions();
+        let (collect, root) = self.malloc_hooks();
+        let pages = (heap + PAGE_SIZE - 1) / PAGE_SIZE;
+        let initial = self.initial_pages.max(pages as u32);
+        let maximum = self
+            .max_pages
+            .map(|max| format!(" {}", max.max(initial)))
+            .unwrap_or_default();
+        format!(
+            "(memory $mem (export \"mem\") {initial}{maximum}) {} {} {} {} {}",
+            format_args!("(global $heap i32 (i32.const {heap}))"),
+            format_args!("(global $allocator (export \"allocator\") (mut i32) (i32.const {heap}))"),
+            // Block is a header of its size class and the content, whose first word links the next free one,
+            // where the size too large for any class traps before the class overflows
+            format_args!(
+                concat!(
+                    "(func $malloc (export \"malloc\") (param $size i32) (result i32) ",
+                    "(local $class i32) (local $head i32) (local $block i32) (local $end i32) ",
+                    "(if (i32.gt_u (local.get $size) (i32.const {limit})) (then (call $out_of_memory))) ",
+                    "(local.set $class (i32.sub (i32.const 32) (i32.clz (i32.add (local.get $size) (i32.const 3))))) ",
+                    "(if (i32.lt_u (local.get $class) (i32.const 3)) (then (local.set $class (i32.const 3)))) ",
+                    "(local.set $head (i32.add (i32.const {table}) (i32.shl (local.get $class) (i32.const 2)))) ",
+                    "(local.set $block (i32.load (local.get $head))) {collect}",
+                    "(if (local.get $block) (then (i32.store (local.get $head) (i32.load offset=4 (local.get $block)))) (else ",
+                    "(local.set $block (global.get $allocator)) ",
+                    "(local.set $end (i32.add (local.get $block) (i32.shl (i32.const 1) (local.get $class)))) ",
+                    "(if (i32.gt_u (local.get $end) (i32.mul (memory.size) (i32.const 65536))) (then ",
+                    "(if (i32.eq (memory.grow (i32.sub (i32.div_u (i32.add (local.get $end) (i32.const 65535)) ",
+                    "(i32.const 65536)) (memory.size))) (i32.const -1)) (then (call $out_of_memory))))) ",
+                    "(global.set $allocator (local.get $end)))) ",
+                    "(i32.store (local.get $block) (local.get $class)) {root}",
+                    "(i32.add (local.get $block) (i32.const 4)))"
+                ),
+                table = table,
+                limit = (1 << 30) - 4,
+                collect = collect,
+                root = root
+            ),
+            // Static data and null pointer aren't allocated, so they're ignored
+            format_args!(
+                concat!(
+                    "(func $free (export \"free\") (param $ptr i32) (local $block i32) (local $head i32) ",
+                    "(if (i32.lt_s (local.get $ptr) (global.get $heap)) (then (return))) ",
+                    "(local.set $block (i32.sub (local.get $ptr) (i32.const 4))) ",
+                    "(local.set $head (i32.add (i32.const {table}) (i32.shl ",
+                    "(i32.and (i32.load (local.get $block)) (i32.const 255)) (i32.const 2)))) ",
+                    "(i32.store offset=4 (local.get $block) (i32.load (local.get $head))) ",
+                    "(i32.store (local.get $head) (local.get $block)))"
+                ),
+                table = table
+            ),
+            // Allocate before copying, as the allocation may grow the memory
+            concat!(
+                "(func $memcpy (param $from i32) (param $size i32) (result i32) (local $ptr i32) ",
+                "(local.set $ptr (call $malloc (local.get $size))) ",
+                "(memory.copy (local.get $ptr) (local.get $from) (local.get $size)) (local.get $ptr))"
+            ),
+        )
+    }
+}
@@ -0,0 +1,161 @@
+use crate::*;
+
+impl Compiler {
+    /// Type of the object to access, that's an error if it may be null
+    pub(crate) fn deref(&mut self, typ: Type, access: &str) -> Option<Type> {
+        let Type::Nullable(_) = typ else {
+            return Some(typ);
+        };
+        let msg = format!(
+            "can't {access} possibly null {}",
+            typ.restore_alias(self).format()
+        );
+        let note = "check the variable or the field with `?` in the condition of `if` or `while`";
+        self.errors
+            .push(Diagnostic::error(msg, self.span).note(note));
+        None
+    }
+
+    /// Possibly null value can't be given where the type doesn't allow null
+    pub(crate) fn expect_non_null(&mut self, expected: &Type, value: &Type) -> Option<()> {
+        let expected = expected.infer(self)?;
+        let value = value.infer(self)?;
+        if matches!(value, Type::Nullable(_)) && !matches!(expected, Type::Nullable(_) | Type::Any)
+        {
+            let [value, expected] = [value, expected].map(|typ| typ.restore_alias(self).format());
+            let msg = format!("possibly null {value} is given where {expected} is expected");
+            let note = format!("declare it as {expected}? to allow null");
+            self.errors
+                .push(Diagnostic::error(msg, self.span).note(note));
+            return None;
+        }
+        Some(())
+    }
+
+    /// Value given where the type is expected, where a new object can put non-null values
+    /// in its nullable fields and elements, as nothing else refers to it yet
+    pub(crate) fn expect_value(&mut self, expected: &Type, value: &Expr) -> Option<()> {
+        let inner: Vec<(&Expr, Type)> = match (new_object(value), expected.infer(self)?.non_null())
+        {
+            (Some(Value::Dict(dict)), Type::Dict(types)) if dict.keys().eq(types.keys()) => {
+                dict.values().zip(types.into_values()).collect()
+            }
+            (Some(Value::Array(array)), Type::Array(typ)) if !array.is_empty() => {
+                array.iter().map(|elm| (elm, *typ.clone())).collect()
+            }
+            _ => {
+                type_check!(value, expected, self)?;
+                let value = value.infer(self)?;
+                return self.expect_non_null(expected, &value);
+            }
+        };
+        for (value, expected) in inner {
+            self.span = value.span();
+            self.expect_value(&expected, value)?;
+        }
+        Some(())
+    }
+
+    /// Run the check or compilation of the code, where the variables that the condition
+    /// checks with `x?` are known not to be null
+    pub(crate) fn narrow<T>(
+        &mut self,
+        cond: &Expr,
+        run: impl FnOnce(&mut Self) -> Option<T>,
+    ) -> Option<T> {
+        let Expr::Operator(oper, _) = cond else {
+            return run(self);
+        };
+        let Op::NullCheck(expr) = oper.as_ref() else {
+            return run(self);
+        };
+        if let Expr::Field(..) = expr {
+            return self.narrow_field(expr, run);
+        }
+        let Expr::Variable(name, _) = expr else {
+            return run(self);
+        };
+        let Some(Type::Nullable(typ)) = self
+            .scope_of(name)
+            .and_then(|scope| scope.get(name))
+            .cloned()
+        else {
+            return run(self);
+        };
+        self.scope_of(name)?.insert(name.clone(), *typ.clone());
+        let result = run(self);
+        // Assignment in the code may have made it possibly null again
+        if let Some(scope) = self.scope_of(name)
+            && !matches!(scope.get(name), Some(Type::Nullable(_)))
+        {
+            scope.insert(name.clone(), Type::Nullable(typ));
+        }
+        result
+    }
+
+    /// Run the code where the field is known not to be null, until its object is assigned
+    fn narrow_field<T>(
+        &mut self,
+        field: &Expr,
+        run: impl FnOnce(&mut Self) -> Option<T>,
+    ) -> Option<T> {
+        let (Some(path), Some(Type::Nullable(typ))) =
+            (field_path(field), self.tolerate(|ctx| field.infer(ctx)))
+        else {
+            return run(self);
+        };
+        let outer = self.narrowed.insert(path.clone(), *typ);
+        let result = run(self);
+        match outer {
+            Some(typ) => self.narrowed.insert(path, typ),
+            None => self.narrowed.swap_remove(&path),
+        };
+        result
+    }
+
+    /// Forget the fields checked not to be null, that the assignment to the target may change
+    pub(crate) fn unnarrow(&mut self, target: &Expr) {
+        match target {
+            Expr::Variable(name, _) => {
+                let root = format!("{name}.");
+                self.narrowed.retain(|path, _| !path.starts_with(&root));
+            }
+            // Other object may be the same one, so the fields of the name are all forgotten
+            Expr::Field(_, key, _) => self
+                .narrowed
+                .retain(|path, _| !path.split('.').skip(1).any(|field| field == key)),
+            _ => {}
+        }
+    }
+
+    /// Type environment that the variable is looked up in
+    fn scope_of(&mut self, name: &str) -> Option<&mut IndexMap<String, Type>> {
+        if self.global.contains_key(name) {
+            Some(&mut self.global)
+        } else if self.variable.contains_key(name) {
+            Some(&mut self.variable)
+        } else if self.argument.contains_key(name) {
+            Some(&mut self.argument)
+        } else {
+            None
+        }
+    }
+}
+
+/// Path of the field access like `a.n.v`, that's narrowed by the name
+pub(crate) fn field_path(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Variable(name, _) => Some(name.clone()),
+        Expr::Field(expr, key, _) => Some(format!("{}.{key}", field_path(expr)?)),
+        _ => None,
+    }
+}
+
+/// Literal of the object that's created by the expression, copied or not
+fn new_object(expr: &Expr) -> Option<&Value> {
+    match expr {
+        Expr::Literal(value @ (Value::Dict(_) | Value::Array(_)), _) => Some(value),
+        Expr::Clone(from, _) => new_object(from),
+        _ => None,
+    }
+}
@@ -0,0 +1,125 @@
+use crate::*;
+
+impl Compiler {
+    /// Whether the value is a struct or an array of the Wasm GC proposal, instead of a pointer
+    pub(crate) fn is_object(&mut self, typ: &Type) -> Option<bool> {
+        let typ = typ.infer(self)?.non_null();
+        Some(self.gc == Gc::Wasm && matches!(typ, Type::Dict(_) | Type::Array(_)))
+    }
+
+    /// Reference type of the object, that's nullable like pointers
+    pub(crate) fn reference(&mut self, typ: &Type) -> Option<String> {
+        let typ = typ.infer(self)?;
+        // Type definition is fixed, while `any` takes a different type at each use
+        if is_generic(&typ) {
+            let typ = typ.format();
+            self.report(format!(
+                "can't define {typ} as Wasm GC type, as it contains `any`"
+            ));
+            return None;
+        }
+        Some(format!("(ref null $type.{})", self.layout(&typ)?))
+    }
+
+    /// Value that variables of the type have before they're assigned
+    pub(crate) fn initial(&mut self, typ: &Type) -> Option<String> {
+        if self.is_object(typ)? {
+            return Some(format!("(ref.null $type.{})", self.layout(typ)?));
+        }
+        Some(format!("({}.const 0)", typ.compile(self)?))
+    }
+
+    /// Type definitions of the objects, in a single recursive group as they may refer to each
+    /// other, and the functions to copy them and to access arrays by wrapped indexes
+    pub(crate) fn expand_objects(&mut self) -> Option<String> {
+        let mut types = vec![];
+        let mut result = vec![];
+        // Fields and elements may add new types while generating
+        let mut index = 0;
+        while index < self.layouts.len() {
+            let typ = self.layouts[index].clone();
+            let object = format!("(ref null $type.{index})");
+            match typ.infer(self)? {
+                Type::Dict(dict) => {
+                    let mut fields = vec![];
+                    let mut copied = vec![];
+                    for (field, typ) in dict.values().enumerate() {
+                        fields.push(format!("(field (mut {}))", typ.compile(self)?));
+                        copied.push(format!(
+                            "(struct.get $type.{index} {field} (local.get $from))"
+                        ));
+                    }
+                    types.push(format!("(type $type.{index} (struct {}))", join!(fields)));
+                    result.push(format!(
+                        "(func $copy.{index} (param $from {object}) (result {object}) (struct.new $type.{index} {}))",
+                        join!(copied)
+                    ));
+                }
+                Type::Array(element) => {
+                    let element = element.compile(self)?;
+                    types.push(format!("(type $type.{index} (array (mut {element})))"));
+                    result.push(format!(
+                        concat!(
+                            "(func $copy.{index} (param $from {object}) (result {object}) (local $to {object}) ",
+                            "(local.set $to (array.new_default $type.{index} (array.len (local.get $from)))) ",
+                            "(array.copy $type.{index} $type.{index} (local.get $to) (i32.const 0) ",
+                            "(local.get $from) (i32.const 0) (array.len (local.get $from))) (local.get $to))"
+                        ),
+                        index = index,
+                        object = object,
+                    ));
+                    // Index is checked or wrapped by the length, as well as the pointer arrays
+                    let wrap = if self.checks.contains(&Check::Bounds) {
+                        "(local.set $index (call $bounds (local.get $index) (array.len (local.get $array))))"
+                    } else {
+                        concat!(
+                            "(local $length i32) (local.set $length (array.len (local.get $array))) ",
+                            "(local.set $index (i32.rem_s (i32.add (i32.rem_s (local.get $index) ",
+                            "(local.get $length)) (local.get $length)) (local.get $length)))"
+                        )
+                    };
+                    result.push(format!(
+                        concat!(
+                            "(func $get.{index} (param $array {object}) (param $index i32) (result {element}) ",
+                            "{wrap} (array.get $type.{index} (local.get $array) (local.get $index)))"
+                        ),
+                        index = index,
+                        object = object,
+                        element = element,
+                        wrap = wrap,
+                    ));
+                    result.push(format!(
+                        concat!(
+                            "(func $set.{index} (param $array {object}) (param $index i32) (param $value {element}) ",
+                            "{wrap} (array.set $type.{index} (local.get $array) (local.get $index) (local.get $value)))"
+                        ),
+                        index = index,
+                        object = object,
+                        element = element,
+                        wrap = wrap,
+                    ));
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+        if types.is_empty() {
+            return Some(String::new());
+        }
+        Some(format!("(rec {}) {}", join!(types), join!(result)))
+    }
+}
+
+/// Whether `any` or a type parameter is in the type, which is only decided at each use
+pub(crate) fn is_generic(typ: &Type) -> bool {
+    match typ {
+        Type::Any | Type::Param(_) | Type::Var(_) => true,
+        Type::Generic(_, args) => args.iter().any(is_generic),
+        Type::Array(element) => is_generic(element),
+        Type::Dict(dict) => dict.values().any(is_generic),
+        Type::Enum(variants) => variants.values().flatten().any(is_generic),
+        Type::Function(args, ret) => args.iter().any(is_generic) || is_generic(ret),
+        Type::Nullable(typ) => is_generic(typ),
+        _ => false,
+    }
+}
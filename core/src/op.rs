@@ -1,5 +1,14 @@
 use crate::*;
 
+/// How operators of the same precedence level are grouped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`
+    Left,
+    /// `a < b < c` is a syntax error
+    NonAssoc,
+}
+
 #[derive(Debug, Clone)]
 pub enum Op {
     Add(Expr, Expr),
@@ -7,6 +16,7 @@ pub enum Op {
     Mul(Expr, Expr),
     Div(Expr, Expr),
     Mod(Expr, Expr),
+    Neg(Expr),
     Shr(Expr, Expr),
     Shl(Expr, Expr),
     Eql(Expr, Expr),
@@ -29,77 +39,17 @@ pub enum Op {
 }
 
 impl Node for Op {
-    fn parse(source: &str) -> Option<Self> {
-        let tokens: Vec<String> = tokenize(source, SPACE.as_ref(), true, true, false)?;
-        // Parsing is from right to left because operator is left-associative
-        let binopergen = |n: usize| {
-            let operator = tokens.get(n)?;
-            let lhs = &join!(tokens.get(..n)?);
-            let rhs = &join!(tokens.get(n + 1..)?);
-            Some(match operator.as_str() {
-                "+" => Op::Add(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "-" => Op::Sub(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "*" => Op::Mul(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "/" => Op::Div(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "%" => Op::Mod(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                ">>" => Op::Shr(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "<<" => Op::Shl(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "==" => Op::Eql(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "!=" => Op::Neq(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "<" => Op::Lt(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                ">" => Op::Gt(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                ">=" => Op::GtEq(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "<=" => Op::LtEq(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "&" => Op::BAnd(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "|" => Op::BOr(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "^" => Op::XOr(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "&&" => Op::LAnd(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                "||" => Op::LOr(Expr::parse(lhs)?, Expr::parse(rhs)?),
-                ":" => Op::Cast(Expr::parse(lhs)?, Type::parse(rhs)?),
-                _ => return None,
-            })
-        };
-        let unaryopergen = || {
-            let op = tokens.first()?.trim();
-            let token = &join!(tokens.get(1..)?);
-            Some(match op {
-                "~" => Op::BNot(Expr::parse(token)?),
-                "!" => Op::LNot(Expr::parse(token)?),
-                "-" => {
-                    let token = Expr::parse(token)?;
-                    Op::Sub(
-                        Expr::Operator(Box::new(Op::Sub(token.clone(), token.clone()))),
-                        token,
-                    )
-                }
-                _ => return None,
-            })
-        };
-        let suffixopergen = || {
-            let op = tokens.last()?.trim();
-            let token = &join!(tokens.get(..tokens.len() - 1)?);
-            Some(match op {
-                "?" => Op::NullCheck(Expr::parse(token)?),
-                "!" => Op::Nullable(Type::parse(token)?),
-                _ => return None,
-            })
-        };
-        for i in 2..tokens.len() {
-            if let Some(op) = binopergen(tokens.len().checked_sub(i)?) {
-                return Some(op);
-            }
-        }
-        if let Some(op) = suffixopergen() {
-            return Some(op);
+    fn parse(p: &mut Parser) -> Option<Self> {
+        let start = p.index;
+        match Expr::parse(p)? {
+            Expr::Operator(oper, _) => Some(*oper),
+            _ => p.error("expected operation", p.span_from(start)),
         }
-        if let Some(op) = unaryopergen() {
-            return Some(op);
-        }
-        None
     }
 
     fn compile(&self, ctx: &mut Compiler) -> Option<String> {
         overload!(self, ctx, compile);
+        let span = ctx.span;
         Some(match self {
             Op::Add(lhs, rhs) => compile_op!("add", ctx, lhs, rhs),
             Op::Sub(lhs, rhs) => compile_op!("sub", ctx, lhs, rhs),
@@ -119,46 +69,83 @@ impl Node for Op {
             Op::LAnd(lhs, rhs) => compile_op!("and", ctx, lhs, rhs),
             Op::LOr(lhs, rhs) => compile_op!("or", ctx, lhs, rhs),
             Op::Eql(lhs, rhs) => compile_op!("eq", ctx, lhs, rhs),
+            // Result of `%` has the sign of the divisor, unlike the remainder instructions
             Op::Mod(lhs, rhs) => {
+                let is_unsigned = matches!(lhs.infer(ctx)?.infer(ctx)?, Type::Unsigned);
                 let typ = lhs.infer(ctx)?.compile(ctx)?;
                 let (lhs, rhs) = (lhs.compile(ctx)?, rhs.compile(ctx)?);
-                if typ == "i32" {
-                    format!("(i32.rem_s (i32.add (i32.rem_s {lhs} {rhs}) {rhs}) {rhs})")
+                if is_unsigned {
+                    format!("(i32.rem_u {lhs} {rhs})")
+                } else if typ.starts_with('i') {
+                    format!("({typ}.rem_s ({typ}.add ({typ}.rem_s {lhs} {rhs}) {rhs}) {rhs})")
+                } else {
+                    format!(
+                        "({typ}.sub {lhs} ({typ}.mul ({typ}.floor ({typ}.div {lhs} {rhs})) {rhs}))"
+                    )
+                }
+            }
+            Op::Neg(term) => {
+                let typ = term.infer(ctx)?.infer(ctx)?;
+                if typ.compile(ctx)?.starts_with('f') {
+                    compile_op!("neg", ctx, term)
                 } else {
-                    format!("(f32.sub {lhs} (f32.mul (f32.floor (f32.div {lhs} {rhs})) {rhs}))")
+                    let zero = Expr::Literal(Value::integer(0, &typ), span);
+                    compile_op!("sub", ctx, zero, term)
                 }
             }
             Op::BNot(lhs) => {
-                let minus_one = Expr::Literal(Value::Integer(-1));
+                let typ = lhs.infer(ctx)?.infer(ctx)?;
+                let minus_one = Expr::Literal(Value::integer(-1, &typ), span);
                 compile_op!("xor", ctx, lhs, minus_one)
             }
             Op::Cast(val, typ) => {
                 let typ = typ.infer(ctx)?;
                 match (val.infer(ctx)?, &typ) {
-                    (Type::Number | Type::Integer, Type::String) => {
-                        let numized = Expr::Operator(Box::new(Op::Cast(val.clone(), Type::Number)));
-                        Expr::Call("to_str".to_owned(), vec![numized]).compile(ctx)?
+                    (Type::Number | Type::Integer | Type::Unsigned, Type::String) => {
+                        let numized = Op::Cast(val.clone(), Type::Number);
+                        let numized = Expr::Operator(Box::new(numized), span);
+                        Expr::Call("to_str".to_owned(), vec![numized], span).compile(ctx)?
                     }
-                    (Type::String, Type::Number | Type::Integer) => {
-                        let parse = Expr::Call("to_num".to_owned(), vec![val.clone()]);
+                    (Type::String, Type::Number | Type::Integer | Type::Unsigned) => {
+                        let parse = Expr::Call("to_num".to_owned(), vec![val.clone()], span);
                         Op::Cast(parse, typ).compile(ctx)?
                     }
-                    (Type::Integer, Type::Number) => {
-                        format!("(f32.convert_i32_s {})", val.compile(ctx)?)
+                    (lhs, rhs) if lhs == *rhs => val.compile(ctx)?,
+                    (lhs, Type::Nullable(inner))
+                        if lhs.infer(ctx)?.compare(&inner.infer(ctx)?, ctx) =>
+                    {
+                        val.compile(ctx)?
                     }
-                    (Type::Number, Type::Integer) => {
-                        format!("(i32.trunc_f32_s {})", val.compile(ctx)?)
+                    (lhs, rhs) if lhs.is_numeric() && rhs.is_numeric() => {
+                        let [from, to] = [lhs.compile(ctx)?, rhs.compile(ctx)?];
+                        let sign = match (lhs, rhs) {
+                            (Type::Unsigned, _) | (_, Type::Unsigned) => "u",
+                            _ => "s",
+                        };
+                        // `int` and `uint` only differ in how operators treat the bits
+                        if from == to {
+                            val.compile(ctx)?
+                        } else {
+                            format!("({to}.{} {})", convert(&from, &to, sign), val.compile(ctx)?)
+                        }
                     }
-                    (lhs, rhs) if lhs == *rhs => val.compile(ctx)?,
                     _ => return None,
                 }
             }
             Op::Transmute(lhs, _) => lhs.compile(ctx)?,
-            Op::NullCheck(expr) => Op::Neq(
-                Expr::Operator(Box::new(Op::Transmute(expr.clone(), Type::Integer))),
-                Expr::Literal(Value::Integer(-1)),
-            )
-            .compile(ctx)?,
+            Op::NullCheck(expr) => {
+                let typ = expr.infer(ctx)?;
+                if ctx.is_object(&typ)? {
+                    format!("(i32.eqz (ref.is_null {}))", expr.compile(ctx)?)
+                } else {
+                    Op::Neq(
+                        Expr::Operator(Box::new(Op::Transmute(expr.clone(), Type::Integer)), span),
+                        Expr::Literal(Value::Integer(-1), span),
+                    )
+                    .compile(ctx)?
+                }
+            }
+            Op::Nullable(typ) if ctx.is_object(typ)? => ctx.initial(typ)?,
             Op::Nullable(_) => Value::Integer(-1).compile(ctx)?,
         })
     }
@@ -173,14 +160,40 @@ impl Node for Op {
             | Op::Mod(lhs, rhs)
             | Op::BAnd(lhs, rhs)
             | Op::BOr(lhs, rhs)
-            | Op::XOr(lhs, rhs) => correct!(lhs, rhs, ctx, Type::Number | Type::Integer),
-            Op::Shr(lhs, rhs) | Op::Shl(lhs, rhs) => correct!(lhs, rhs, ctx, Type::Integer),
+            | Op::XOr(lhs, rhs) => correct!(
+                lhs,
+                rhs,
+                ctx,
+                Type::Integer | Type::Number | Type::Long | Type::Double | Type::Unsigned
+            ),
+            Op::Shr(lhs, rhs) | Op::Shl(lhs, rhs) => {
+                correct!(lhs, rhs, ctx, Type::Integer | Type::Long | Type::Unsigned)
+            }
             Op::Eql(lhs, rhs) | Op::Neq(lhs, rhs) => {
-                correct!(lhs, rhs, ctx, Type::Number | Type::Integer | Type::Enum(_))?;
+                correct!(
+                    lhs,
+                    rhs,
+                    ctx,
+                    Type::Integer
+                        | Type::Number
+                        | Type::Long
+                        | Type::Double
+                        | Type::Unsigned
+                        | Type::Enum(_)
+                )?;
+                if lhs.infer(ctx)?.infer(ctx)?.has_fields() {
+                    ctx.report("can't compare enums with fields, match them instead");
+                    return None;
+                }
                 Some(Type::Bool)
             }
             Op::Lt(lhs, rhs) | Op::Gt(lhs, rhs) | Op::LtEq(lhs, rhs) | Op::GtEq(lhs, rhs) => {
-                correct!(lhs, rhs, ctx, Type::Number | Type::Integer)?;
+                correct!(
+                    lhs,
+                    rhs,
+                    ctx,
+                    Type::Integer | Type::Number | Type::Long | Type::Double | Type::Unsigned
+                )?;
                 Some(Type::Bool)
             }
             Op::LAnd(lhs, rhs) | Op::LOr(lhs, rhs) => {
@@ -196,23 +209,35 @@ impl Node for Op {
                 let lhs = lhs.infer(ctx)?;
                 let rhs = rhs.infer(ctx)?;
                 match (lhs.clone(), rhs.clone()) {
-                    (Type::Number, Type::Integer) => Some(Type::Integer),
-                    (Type::Integer, Type::Number) => Some(Type::Number),
-                    (Type::String, Type::Integer | Type::Number) => Some(rhs),
-                    (Type::Integer | Type::Number, Type::String) => Some(Type::String),
+                    (lhs, rhs) if lhs.is_numeric() && rhs.is_numeric() => Some(rhs),
+                    (Type::String, Type::Integer | Type::Number | Type::Unsigned) => Some(rhs),
+                    (Type::Integer | Type::Number | Type::Unsigned, Type::String) => {
+                        Some(Type::String)
+                    }
+                    // Value is allowed to be null, as the field or element it's put in is declared
+                    (lhs, Type::Nullable(inner))
+                        if lhs.infer(ctx)?.compare(&inner.infer(ctx)?, ctx) =>
+                    {
+                        Some(rhs)
+                    }
                     (lhs, rhs) if lhs == rhs => Some(lhs),
                     _ => {
                         let [lhs, rhs] = [lhs.format(), rhs.format()];
-                        let msg = format!("type {lhs} can't convert to {rhs}");
-                        ctx.error = Some(msg);
-                        return None;
+                        ctx.report(format!("type {lhs} can't convert to {rhs}"));
+                        None
                     }
                 }
             }
-            Op::BNot(lhs) => {
-                type_check!(lhs, Type::Integer, ctx)?;
-                Some(Type::Integer)
+            Op::Neg(term) => {
+                let typ = term.infer(ctx)?;
+                if typ.infer(ctx)?.is_numeric() {
+                    Some(typ)
+                } else {
+                    ctx.report(format!("can't negate {}", typ.format()));
+                    None
+                }
             }
+            Op::BNot(lhs) => correct!(lhs, lhs, ctx, Type::Integer | Type::Long | Type::Unsigned),
             Op::Transmute(lhs, rhs) => {
                 lhs.infer(ctx)?;
                 rhs.infer(ctx)
@@ -221,25 +246,90 @@ impl Node for Op {
                 if is_ptr!(expr.infer(ctx)?, ctx) {
                     Some(Type::Bool)
                 } else {
-                    let errmsg = format!("can't null-check primitive typed value");
-                    ctx.error = Some(errmsg);
-                    return None;
+                    ctx.report("can't null-check primitive typed value");
+                    None
                 }
             }
             Op::Nullable(typ) => {
                 if is_ptr!(typ, ctx) {
-                    Some(typ.clone())
+                    Some(typ.clone().nullable())
                 } else {
-                    let errmsg = format!("primitive types are not nullable");
-                    ctx.error = Some(errmsg);
-                    return None;
+                    ctx.report("primitive types are not nullable");
+                    None
                 }
             }
         }
     }
 }
 
+/// Instruction to convert a number of the Wasm type into another, where the integer is
+/// signed or unsigned by the `sign`
+fn convert(from: &str, to: &str, sign: &str) -> String {
+    match (from.starts_with('f'), to.starts_with('f')) {
+        (false, true) => format!("convert_{from}_{sign}"),
+        (true, false) => format!("trunc_{from}_{sign}"),
+        (false, false) if to == "i64" => format!("extend_i32_{sign}"),
+        (false, false) => "wrap_i64".to_string(),
+        (true, true) if to == "f64" => "promote_f32".to_string(),
+        (true, true) => "demote_f64".to_string(),
+    }
+}
+
 impl Op {
+    /// Precedence level of binary operators, where larger one binds tighter.
+    /// Prefix `-` `!` `~` bind tighter than any of them, and suffix `?` `!` tighter still.
+    /// `=` isn't an operator in expressions, it only separates `let` statements
+    pub fn precedence(op: &str) -> Option<(usize, Assoc)> {
+        Some(match op {
+            "||" => (1, Assoc::Left),
+            "&&" => (2, Assoc::Left),
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => (3, Assoc::NonAssoc),
+            "|" => (4, Assoc::Left),
+            "^" => (5, Assoc::Left),
+            "&" => (6, Assoc::Left),
+            "<<" | ">>" => (7, Assoc::Left),
+            "+" | "-" => (8, Assoc::Left),
+            "*" | "/" | "%" => (9, Assoc::Left),
+            ":" => (10, Assoc::Left),
+            _ => return None,
+        })
+    }
+
+    /// Binary operation of the operator token
+    pub fn binary(op: &str) -> Option<fn(Expr, Expr) -> Op> {
+        Some(match op {
+            "+" => Op::Add,
+            "-" => Op::Sub,
+            "*" => Op::Mul,
+            "/" => Op::Div,
+            "%" => Op::Mod,
+            ">>" => Op::Shr,
+            "<<" => Op::Shl,
+            "==" => Op::Eql,
+            "!=" => Op::Neq,
+            "<" => Op::Lt,
+            ">" => Op::Gt,
+            ">=" => Op::GtEq,
+            "<=" => Op::LtEq,
+            "&" => Op::BAnd,
+            "|" => Op::BOr,
+            "^" => Op::XOr,
+            "&&" => Op::LAnd,
+            "||" => Op::LOr,
+            _ => return None,
+        })
+    }
+
+    /// Prefix operation of the operator token
+    pub fn unary(op: &str) -> Option<fn(Expr) -> Op> {
+        Some(match op {
+            "~" => Op::BNot,
+            "!" => Op::LNot,
+            "-" => Op::Neg,
+            _ => return None,
+        })
+    }
+
     pub fn overload_id_table() -> IndexMap<String, usize> {
         IndexMap::from([
             ("+".to_owned(), 1),
@@ -261,6 +351,7 @@ impl Op {
             ("&&".to_owned(), 18),
             ("||".to_owned(), 19),
             (":".to_owned(), 20),
+            ("neg".to_owned(), 21),
         ])
     }
 
@@ -285,6 +376,7 @@ impl Op {
             Op::LAnd(_, _) => 18,
             Op::LOr(_, _) => 19,
             Op::Cast(_, _) => 20,
+            Op::Neg(_) => 21,
             _ => return None,
         })
     }
@@ -0,0 +1,232 @@
+use crate::*;
+
+/// Cursor over the token stream, which nodes consume by recursive descent
+pub struct Parser {
+    tokens: Vec<(Token, Span)>,
+    /// Position of the current token
+    pub index: usize,
+    /// Errors that occurred during parsing
+    pub errors: Vec<Diagnostic>,
+    /// Comments with their spans, which are left out of the tokens
+    pub comments: Vec<(String, Span)>,
+    /// Span of the end of input
+    eof: Span,
+}
+
+impl Parser {
+    pub fn new(source: &str, offset: usize) -> Parser {
+        let (tokens, errors) = match tokenize(source, offset) {
+            Ok(tokens) => (tokens, vec![]),
+            Err(err) => (vec![], vec![err]),
+        };
+        let (comments, tokens): (Vec<_>, Vec<_>) = tokens
+            .into_iter()
+            .partition(|(token, _)| matches!(token, Token::Comment(_)));
+        let comments = comments
+            .into_iter()
+            .filter_map(|(token, span)| match token {
+                Token::Comment(text) => Some((text, span)),
+                _ => None,
+            })
+            .collect();
+        Parser {
+            tokens,
+            index: 0,
+            errors,
+            comments,
+            eof: Span::new(offset + source.len(), offset + source.len()),
+        }
+    }
+
+    /// Parse the whole source code as the node
+    pub fn parse<T: Node>(source: &str, offset: usize) -> Result<T, Vec<Diagnostic>> {
+        Parser::new(source, offset).parse_all()
+    }
+
+    /// Parse all the tokens as the node
+    pub fn parse_all<T: Node>(&mut self) -> Result<T, Vec<Diagnostic>> {
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
+        }
+        let node = T::parse(self);
+        if node.is_some() && self.peek().is_some() {
+            let found = self.found();
+            self.error::<()>(format!("unexpected {found}"), self.span());
+        }
+        match node {
+            Some(node) if self.errors.is_empty() => Ok(node),
+            _ => {
+                if self.errors.is_empty() {
+                    let start = self
+                        .tokens
+                        .first()
+                        .map_or(self.eof.start, |(_, span)| span.start);
+                    self.error::<()>("invalid syntax", Span::new(start, self.eof.end));
+                }
+                Err(std::mem::take(&mut self.errors))
+            }
+        }
+    }
+
+    /// Parse source code embedded in a token, such as a formatted string
+    pub fn embed<T: Node>(&mut self, source: &str, offset: usize) -> Option<T> {
+        Parser::parse(source, offset)
+            .map_err(|errors| self.errors.extend(errors))
+            .ok()
+    }
+
+    pub fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    /// Token that's `n` tokens ahead of the current one
+    pub fn peek_at(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.index + n).map(|(token, _)| token)
+    }
+
+    /// Span of the current token
+    pub fn span(&self) -> Span {
+        self.tokens
+            .get(self.index)
+            .map_or(self.eof, |(_, span)| *span)
+    }
+
+    /// Span from the token at `start` to the last consumed one
+    pub fn span_from(&self, start: usize) -> Span {
+        match (self.tokens.get(start), self.index.checked_sub(1)) {
+            (Some((_, first)), Some(last)) if start < self.index => first.to(self.tokens[last].1),
+            _ => self.span(),
+        }
+    }
+
+    /// Move on to the next token
+    pub fn advance(&mut self) {
+        self.index = (self.index + 1).min(self.tokens.len());
+    }
+
+    /// Consume the current token if it's the expected one
+    pub fn eat(&mut self, token: Token) -> Option<Span> {
+        let span = self.span();
+        (self.peek() == Some(&token)).then(|| {
+            self.index += 1;
+            span
+        })
+    }
+
+    /// Consume the expected token, or report that it's missing
+    pub fn expect(&mut self, token: Token) -> Option<Span> {
+        self.eat(token.clone())
+            .or_else(|| self.expected(&token.format()))
+    }
+
+    pub fn ident(&mut self) -> Option<String> {
+        let Some(Token::Ident(name)) = self.peek().cloned() else {
+            return self.expected("identifier");
+        };
+        self.index += 1;
+        Some(name)
+    }
+
+    /// Consume the closing bracket, or report the bracket that's not closed
+    pub fn close(&mut self, open: Span, close: char) -> Option<()> {
+        if self.eat(Token::Punct(close)).is_some() {
+            return Some(());
+        }
+        let open_char = match close {
+            ')' => '(',
+            ']' => '[',
+            _ => '{',
+        };
+        let message = format!("expected `{close}`, found {}", self.found());
+        let err = Diagnostic::error(message, self.span());
+        self.errors
+            .push(err.label(open, format!("unclosed `{open_char}`")));
+        None
+    }
+
+    /// Items separated by commas until the closing bracket
+    pub fn list<T>(
+        &mut self,
+        open: Span,
+        close: char,
+        mut item: impl FnMut(&mut Parser) -> Option<T>,
+    ) -> Option<Vec<T>> {
+        let mut result = vec![];
+        while self.peek().is_some() && self.peek() != Some(&Token::Punct(close)) {
+            result.push(item(self)?);
+            if self.eat(Token::Punct(',')).is_none() {
+                break;
+            }
+        }
+        self.close(open, close)?;
+        Some(result)
+    }
+
+    /// Items separated by commas in the angle brackets, like type parameters
+    pub fn angle<T>(&mut self, mut item: impl FnMut(&mut Parser) -> Option<T>) -> Option<Vec<T>> {
+        self.expect(Token::Operator("<"))?;
+        let mut result = vec![item(self)?];
+        while self.eat(Token::Punct(',')).is_some() {
+            result.push(item(self)?);
+        }
+        match self.tokens.get_mut(self.index) {
+            Some((Token::Operator(">"), _)) => self.advance(),
+            // `>>` closes the nested ones, so it's split leaving `>` for the outer
+            Some((token @ Token::Operator(">>"), span)) => {
+                *token = Token::Operator(">");
+                span.start += 1;
+            }
+            _ => return self.expected("`>`"),
+        }
+        Some(result)
+    }
+
+    /// Parse the tokens consumed since `start` again as another kind of node
+    pub fn reparse<T: Node>(&mut self, start: usize, what: &str) -> Option<T> {
+        let end = self.index;
+        self.index = start;
+        let node = T::parse(self);
+        if node.is_some() && self.index != end {
+            self.index = end;
+            return self.error(format!("expected {what}"), self.span_from(start));
+        }
+        node
+    }
+
+    /// Whether the current statement ends here
+    pub fn is_terminated(&self) -> bool {
+        matches!(self.peek(), None | Some(Token::Punct(';' | '}')))
+    }
+
+    /// Skip the broken statement that starts at `start`, to parse the next one
+    pub fn recover(&mut self, start: usize) {
+        let mut depth = 0usize;
+        self.index = start;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Punct(';' | '}') if depth == 0 => return,
+                Token::Punct('(' | '[' | '{') => depth += 1,
+                Token::Punct(')' | ']' | '}') => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.index += 1;
+        }
+    }
+
+    pub fn error<T>(&mut self, message: impl ToString, span: Span) -> Option<T> {
+        self.errors.push(Diagnostic::error(message, span));
+        None
+    }
+
+    /// Report that the current token isn't what's expected
+    pub fn expected<T>(&mut self, what: &str) -> Option<T> {
+        let message = format!("expected {what}, found {}", self.found());
+        self.error(message, self.span())
+    }
+
+    /// Description of the current token
+    fn found(&self) -> String {
+        self.peek()
+            .map_or("end of input".to_string(), |token| token.format())
+    }
+}
@@ -1,19 +1,24 @@
-use crate::*;
+use crate::{generic::function_name, *};
 
 #[derive(Clone, Debug)]
 pub enum Stmt {
-    Expr(Expr),
-    Let(Scope, Expr, Expr),
-    If(Expr, Expr, Option<Box<Stmt>>),
-    While(Expr, Expr),
-    Type(String, Type),
-    Try(Expr, Box<Stmt>),
-    Module(String, Vec<String>, Expr),
-    Overload(usize, (Type, Type), String),
-    Import(Signature),
-    Return(Option<Expr>),
-    Break,
-    Next,
+    Expr(Expr, Span),
+    Let(Scope, Expr, Expr, Span),
+    /// Function with the type parameters, written `let name<T>(x: T) = ...`
+    Generic(Scope, Vec<String>, Expr, Expr, Span),
+    If(Expr, Expr, Option<Box<Stmt>>, Span),
+    /// Arms with the variant that they match, or `None` for `_` that matches the rest
+    Match(Expr, Vec<(Option<Expr>, Expr)>, Span),
+    While(Expr, Expr, Span),
+    /// Type alias, with the type parameters if it's generic
+    Type(String, Vec<String>, Type, Span),
+    Try(Expr, Box<Stmt>, Span),
+    Module(String, Vec<String>, Expr, Span),
+    Overload(usize, (Type, Type), String, Span),
+    Import(Signature, Span),
+    Return(Option<Expr>, Span),
+    Break(Span),
+    Next(Span),
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -23,147 +28,172 @@ pub enum Scope {
 }
 
 impl Node for Stmt {
-    fn parse(source: &str) -> Option<Self> {
-        let source = source.trim();
-        let tokens: Vec<String>;
-        macro_rules! parse {
-            ($node: ident, $range: expr) => {
-                $node::parse(&join!(tokens.get($range)?))?
-            };
-        }
-        if let Some(source) = source.strip_prefix("if ") {
-            tokens = tokenize(source, SPACE.as_ref(), false, true, false)?;
-            let then = tokens.iter().position(|i| i == "then")?;
-            if let Some(r#else) = tokens.iter().position(|i| i == "else") {
-                Some(Stmt::If(
-                    parse!(Expr, 0..then),
-                    parse!(Expr, then + 1..r#else),
-                    Some(Box::new(parse!(Stmt, r#else + 1..))),
-                ))
-            } else {
-                Some(Stmt::If(
-                    parse!(Expr, 0..then),
-                    parse!(Expr, then + 1..),
-                    None,
-                ))
-            }
-        } else if let Some(source) = source.strip_prefix("while ") {
-            tokens = tokenize(source, SPACE.as_ref(), false, true, false)?;
-            let r#loop = tokens.iter().position(|i| i == "loop")?;
-            Some(Stmt::While(
-                parse!(Expr, 0..r#loop),
-                parse!(Expr, r#loop + 1..),
-            ))
-        } else if let Some(source) = source.strip_prefix("try ") {
-            tokens = tokenize(source, SPACE.as_ref(), false, true, false)?;
-            let r#catch = tokens.iter().position(|i| i == "catch")?;
-            Some(Stmt::Try(
-                parse!(Expr, 0..r#catch),
-                Box::new(parse!(Stmt, r#catch + 1..)),
-            ))
-        } else if let Some(source) = source.strip_prefix("use ") {
-            let token = tokenize(&source, &["("], false, true, true)?;
-            let args = token.last()?.get(1..token.last()?.len() - 1)?;
-            let args = tokenize(args, &[","], false, true, false)?;
-            let name = token.get(..token.len() - 1)?.concat();
-            let args = args
-                .iter()
-                .map(|i| Type::parse(&i))
-                .collect::<Option<Vec<_>>>()?;
-            let Expr::Variable(name) = Expr::parse(&name)? else {
-                return None;
-            };
-            Some(Stmt::Expr(Expr::Macro(name, args)))
-        } else if let Some(token) = source.strip_prefix("let ") {
-            if let Some((name, value)) = token.split_once("=") {
-                let (name, value) = (Expr::parse(name)?, Expr::parse(value)?);
-                Some(Stmt::Let(Scope::Local, name, value))
-            } else {
-                let source = Op::parse(token)?;
-                macro_rules! assign_with {
-                    ($op: ident) => {
-                        if let Op::$op(name, value) = source {
-                            let value = Expr::Operator(Box::new(Op::$op(name.clone(), value)));
-                            return Some(Stmt::Let(Scope::Local, name, value));
-                        }
+    fn parse(p: &mut Parser) -> Option<Self> {
+        let start = p.index;
+        match p.peek().cloned() {
+            Some(Token::Keyword("if")) => {
+                p.advance();
+                let cond = Expr::parse(p)?;
+                p.expect(Token::Keyword("then"))?;
+                let then = Expr::parse(p)?;
+                let r#else = if p.eat(Token::Keyword("else")).is_some() {
+                    Some(Box::new(Stmt::parse(p)?))
+                } else {
+                    None
+                };
+                Some(Stmt::If(cond, then, r#else, p.span_from(start)))
+            }
+            Some(Token::Keyword("match")) => {
+                p.advance();
+                let expr = Expr::parse(p)?;
+                let open = p.expect(Token::Punct('{'))?;
+                let arms = p.list(open, '}', |p| {
+                    let pattern = if p.eat(Token::Ident("_".to_string())).is_some() {
+                        None
+                    } else {
+                        Some(Expr::parse(p)?)
                     };
+                    p.expect(Token::Operator("=>"))?;
+                    Some((pattern, Expr::parse(p)?))
+                })?;
+                Some(Stmt::Match(expr, arms, p.span_from(start)))
+            }
+            Some(Token::Keyword("while")) => {
+                p.advance();
+                let cond = Expr::parse(p)?;
+                p.expect(Token::Keyword("loop"))?;
+                let body = Expr::parse(p)?;
+                Some(Stmt::While(cond, body, p.span_from(start)))
+            }
+            Some(Token::Keyword("try")) => {
+                p.advance();
+                let expr = Expr::parse(p)?;
+                p.expect(Token::Keyword("catch"))?;
+                let catch = Stmt::parse(p)?;
+                Some(Stmt::Try(expr, Box::new(catch), p.span_from(start)))
+            }
+            Some(Token::Ident(word))
+                if word == "use" && matches!(p.peek_at(1), Some(Token::Ident(_))) =>
+            {
+                p.advance();
+                let name = p.ident()?;
+                let open = p.expect(Token::Punct('('))?;
+                let args = p.list(open, ')', Type::parse)?;
+                let span = p.span_from(start);
+                Some(Stmt::Expr(Expr::Macro(name, args, span), span))
+            }
+            Some(Token::Keyword("let")) => {
+                p.advance();
+                Stmt::parse_let(p, start, Scope::Local)
+            }
+            Some(Token::Keyword("pub")) => {
+                p.advance();
+                p.expect(Token::Keyword("let"))?;
+                Stmt::parse_let(p, start, Scope::Global)
+            }
+            Some(Token::Keyword("type")) => {
+                p.advance();
+                let name = p.ident()?;
+                let params = if p.peek() == Some(&Token::Operator("<")) {
+                    p.angle(Parser::ident)?
+                } else {
+                    vec![]
+                };
+                p.expect(Token::Operator("="))?;
+                let value = Type::parse(p)?;
+                Some(Stmt::Type(name, params, value, p.span_from(start)))
+            }
+            Some(Token::Ident(word))
+                if word == "module" && matches!(p.peek_at(1), Some(Token::Ident(_))) =>
+            {
+                p.advance();
+                let name = p.ident()?;
+                let open = p.expect(Token::Punct('('))?;
+                let args = p.list(open, ')', Parser::ident)?;
+                p.expect(Token::Operator("="))?;
+                let value = Expr::parse(p)?;
+                Some(Stmt::Module(name, args, value, p.span_from(start)))
+            }
+            Some(Token::Keyword("overload")) => {
+                p.advance();
+                let name = p.ident()?;
+                p.expect(Token::Operator("="))?;
+                // Unary negation `overload name = -T`
+                if p.eat(Token::Operator("-")).is_some() {
+                    let id = Op::overload_id_table()["neg"];
+                    let typ = Type::parse(p)?;
+                    return Some(Stmt::Overload(
+                        id,
+                        (typ, Type::Void),
+                        name,
+                        p.span_from(start),
+                    ));
                 }
-                assign_with!(Add);
-                assign_with!(Sub);
-                assign_with!(Mul);
-                assign_with!(Div);
-                assign_with!(Mod);
-                None
-            }
-        } else if let Some(token) = source.strip_prefix("pub ") {
-            let Stmt::Let(Scope::Local, name, value) = Stmt::parse(token)? else {
-                return None;
-            };
-            Some(Stmt::Let(Scope::Global, name, value))
-        } else if let Some(source) = source.strip_prefix("type ") {
-            let (name, value) = source.split_once("=")?;
-            let Some(Expr::Variable(name)) = Expr::parse(name) else {
-                return None;
-            };
-            Some(Stmt::Type(name, Type::parse(value)?))
-        } else if let Some(source) = source.strip_prefix("module ") {
-            let (head, value) = source.split_once("=")?;
-            let Expr::Call(name, args) = Expr::parse(head)? else {
-                return None;
-            };
-            let args = args
-                .iter()
-                .map(|x| {
-                    let Expr::Variable(x) = x else { return None };
-                    Some(x.clone())
-                })
-                .collect::<Option<Vec<_>>>()?;
-            Some(Stmt::Module(name, args, Expr::parse(value)?))
-        } else if let Some(source) = source.strip_prefix("overload ") {
-            let (name, value) = source.split_once("=")?;
-            tokens = tokenize(value, SPACE.as_ref(), true, true, false)?;
-            let [lhs, op, rhs] = tokens.as_slice() else {
-                return None;
-            };
-            Some(Stmt::Overload(
-                *Op::overload_id_table().get(op)?,
-                (Type::parse(lhs)?, Type::parse(rhs)?),
-                name.trim().to_owned(),
-            ))
-        } else if let Some(source) = source.strip_prefix("import ") {
-            let (body, ret) = source.rsplit_once(":").or(Some((source, "void")))?;
-            let (name, args) = body.split_once("(")?;
-            let mut name = name.trim().to_string();
-            if !is_identifier(&mut name) {
-                return None;
-            };
-            let args = tokenize(&args.trim().replace(")", ""), &[","], false, true, false)?
-                .iter()
-                .map(|x| Type::parse(x))
-                .collect::<Option<Vec<Type>>>()?;
-            Some(Stmt::Import((name, args, Type::parse(ret)?)))
-        } else if let Some(source) = source.strip_prefix("return ") {
-            Some(Stmt::Return(Some(Expr::parse(source)?)))
-        } else if source == "return" {
-            Some(Stmt::Return(None))
-        } else if source == "next" {
-            Some(Stmt::Next)
-        } else if source == "break" {
-            Some(Stmt::Break)
-        } else {
-            Some(Stmt::Expr(Expr::parse(source)?))
+                let lhs = Type::parse(p)?;
+                let id = match p.peek() {
+                    Some(Token::Operator(op)) => Op::overload_id_table().get(*op).copied(),
+                    _ => None,
+                };
+                let Some(id) = id else {
+                    return p.expected("overloadable operator");
+                };
+                p.advance();
+                let rhs = Type::parse(p)?;
+                Some(Stmt::Overload(id, (lhs, rhs), name, p.span_from(start)))
+            }
+            Some(Token::Keyword("import")) => {
+                p.advance();
+                let span = p.span();
+                let name = p.ident()?;
+                if BUILTIN.contains(&name.as_str()) {
+                    return p.error(
+                        format!("can't import function `{name}`, as it's built-in"),
+                        span,
+                    );
+                }
+                let open = p.expect(Token::Punct('('))?;
+                let args = p.list(open, ')', Type::parse)?;
+                let ret = if p.eat(Token::Operator(":")).is_some() {
+                    Type::parse(p)?
+                } else {
+                    Type::Void
+                };
+                Some(Stmt::Import((name, args, ret), p.span_from(start)))
+            }
+            Some(Token::Keyword("return")) => {
+                p.advance();
+                let value = if p.is_terminated() {
+                    None
+                } else {
+                    Some(Expr::parse(p)?)
+                };
+                Some(Stmt::Return(value, p.span_from(start)))
+            }
+            Some(Token::Keyword("next")) => {
+                p.advance();
+                Some(Stmt::Next(p.span_from(start)))
+            }
+            Some(Token::Keyword("break")) => {
+                p.advance();
+                Some(Stmt::Break(p.span_from(start)))
+            }
+            _ => {
+                let expr = Expr::parse(p)?;
+                Some(Stmt::Expr(expr, p.span_from(start)))
+            }
         }
     }
 
     fn compile(&self, ctx: &mut Compiler) -> Option<String> {
-        Some(match self {
-            Stmt::Expr(expr) => expr.compile(ctx)?,
-            Stmt::If(cond, then, r#else) => {
+        let outer = std::mem::replace(&mut ctx.span, self.span());
+        let code = match self {
+            Stmt::Expr(expr, _) => expr.compile(ctx)?,
+            Stmt::If(cond, then, r#else, _) => {
                 format!(
                     "(if {} {} (then {}) {})",
                     compile_return!(self.infer(ctx)?, ctx),
                     cond.compile(ctx)?,
-                    then.compile(ctx)?,
+                    ctx.narrow(cond, |ctx| then.compile(ctx))?,
                     if let Some(r#else) = r#else {
                         format!("(else {})", r#else.compile(ctx)?)
                     } else {
@@ -171,45 +201,106 @@ impl Node for Stmt {
                     },
                 )
             }
-            Stmt::While(cond, body) => {
+            // Arm blocks are nested so that the branch to each one jumps to its code
+            Stmt::Match(expr, arms, _) => {
+                let table = Stmt::match_table(expr, arms, ctx)?;
+                let targets: Vec<_> = table.iter().map(|arm| format!("$arm.{arm}")).collect();
+                let typ = expr.infer(ctx)?.infer(ctx)?;
+                // Object is kept in a local to branch by its tag, and to load the fields in the arm
+                let (subject, tag) = if typ.has_fields() {
+                    let subject = format!("match.{}", ctx.count);
+                    ctx.count += 1;
+                    ctx.variable.insert(subject.clone(), typ.clone());
+                    let value = expr.compile(ctx)?;
+                    let assign = ctx.assign(Scope::Local, &subject, &typ, value)?;
+                    let tag = format!("{assign} (i32.load (local.get ${subject}))");
+                    (Some(subject), tag)
+                } else {
+                    (None, expr.compile(ctx)?)
+                };
+                let mut code = format!("(br_table {} {} {tag})", join!(targets), targets.last()?);
+                for (index, (pattern, body)) in arms.iter().enumerate() {
+                    let mut bind = vec![];
+                    if let (Some(pattern), Some(subject)) = (pattern, &subject) {
+                        for (name, typ, offset) in ctx.bindings(pattern)? {
+                            let field = format!(
+                                "({}.load offset={offset} (local.get ${subject}))",
+                                typ.compile(ctx)?
+                            );
+                            ctx.variable.insert(name.clone(), typ.clone());
+                            bind.push(ctx.assign(Scope::Local, &name, &typ, field)?);
+                        }
+                    }
+                    code = format!(
+                        "(block $arm.{index} {code}) {} {} (br $match)",
+                        join!(bind),
+                        body.compile(ctx)?
+                    );
+                }
+                format!(
+                    "(block $match {} {code})",
+                    compile_return!(self.infer(ctx)?, ctx)
+                )
+            }
+            Stmt::While(cond, body, span) => {
                 let in_while = ctx.in_while;
                 ctx.in_while = true;
-                let body = body.compile(ctx)?;
-                let next = Stmt::Next.compile(ctx)?;
+                let mut code = ctx.narrow(cond, |ctx| body.compile(ctx))?;
+                // Body is a statement of its own, whose value is thrown away at each iteration
+                if !matches!(ctx.narrow(cond, |ctx| body.infer(ctx))?, Type::Void) {
+                    code.push_str("(drop)");
+                }
+                let body = ctx.statement(code);
+                let next = Stmt::Next(*span).compile(ctx)?;
                 ctx.in_while = in_while;
                 format!(
                     "(block $outer (loop $while_start (br_if $outer (i32.eqz {})) {body} {next}))",
                     cond.compile(ctx)?,
                 )
             }
-            Stmt::Next => "(br $while_start)".to_string(),
-            Stmt::Break => "(br $outer)".to_string(),
-            Stmt::Let(scope, name, value) => match name {
-                Expr::Variable(name) => match scope {
+            Stmt::Next(_) => "(br $while_start)".to_string(),
+            Stmt::Break(_) => "(br $outer)".to_string(),
+            Stmt::Let(scope, name, value, span) => match name {
+                Expr::Variable(name, _) => match scope {
                     Scope::Local => {
                         let typ = value.infer(ctx)?;
+                        let value = value.compile(ctx)?;
                         if !ctx.argument.contains_key(name) {
-                            ctx.variable.insert(name.to_string(), typ);
+                            // Variable keeps its type as in the inference, unless null is given
+                            let is_null = matches!(typ.infer(ctx)?, Type::Nullable(_));
+                            let declared = match ctx.variable.get(name) {
+                                Some(exist) if is_null => exist.clone().nullable(),
+                                Some(exist) => exist.clone(),
+                                None => typ.clone(),
+                            };
+                            ctx.variable.insert(name.to_string(), declared);
                         }
-                        format!("(local.set ${name} {})", value.compile(ctx)?)
+                        ctx.assign(*scope, name, &typ, value)?
                     }
                     Scope::Global => {
                         let typ = value.infer(ctx)?;
                         if !ctx.global.contains_key(name) {
-                            ctx.global.insert(name.to_string(), typ);
+                            ctx.global.insert(name.to_string(), typ.clone());
                         }
-                        format!("(global.set ${name} {})", value.compile(ctx)?)
+                        let value = value.compile(ctx)?;
+                        ctx.assign(*scope, name, &typ, value)?
                     }
                 },
-                Expr::Call(name, _) => {
-                    self.infer(ctx);
+                Expr::Call(name, _, _) => {
+                    ctx.tolerate(|ctx| self.infer(ctx));
+                    // Function generalized over its arguments is compiled at each call instead
+                    if ctx.template.contains_key(name) {
+                        ctx.span = outer;
+                        return Some(String::new());
+                    }
                     let var_ctx = ctx.variable.clone();
                     let arg_ctx = ctx.argument.clone();
                     let function = ctx.function.get(name).or(ctx.export.get(name))?.clone();
                     ctx.variable = function.variables.clone();
                     ctx.argument = function.arguments.clone();
+                    let narrowed = std::mem::take(&mut ctx.narrowed);
                     let code = format!(
-                        "(func ${name} {pub} {args} {ret} {locals} {body})",
+                        "(func ${name} {pub} {args} {ret} {locals} {temp} {body})",
                         args = join!(
                             &function
                                 .arguments
@@ -222,108 +313,190 @@ impl Node for Stmt {
                         ),
                         ret = compile_return!(function.returns, ctx),
                         pub = if let Scope::Global = scope { format!("(export \"{name}\")") } else { String::new() },
-                        body = value.compile(ctx)?, locals = expand_local!(ctx)
+                        body = {
+                            let body = value.compile(ctx)?;
+                            let prologue = ctx.prologue()?;
+                            format!("{prologue} {}", ctx.epilogue(&function.returns, body)?)
+                        },
+                        locals = expand_local!(ctx),
+                        temp = ctx.epilogue_local(&function.returns)?
                     );
                     ctx.declare.insert(name.to_owned(), code);
                     ctx.variable = var_ctx;
                     ctx.argument = arg_ctx;
+                    ctx.narrowed = narrowed;
                     String::new()
                 }
-                Expr::Operator(oper) => {
+                Expr::Operator(oper, _) => {
                     self.infer(ctx)?;
                     let Op::Cast(func, _) = *oper.clone() else {
                         return None;
                     };
-                    Stmt::Let(*scope, func, value.clone()).compile(ctx)?
+                    Stmt::Let(*scope, func, value.clone(), *span).compile(ctx)?
                 }
-                Expr::Index(array, index) => {
-                    let typ = array.infer(ctx)?;
-                    let Type::Array(inner_typ) = typ.clone() else {
-                        return None;
-                    };
-                    type_check!(inner_typ, value.infer(ctx)?, ctx)?;
-                    let addr = Box::new(address_calc!(array, index, typ));
-                    Expr::Poke(addr, Box::new(value.clone())).compile(ctx)?
+                Expr::Index(array, index, _) => {
+                    let (typ, inner_typ) = Stmt::slot(name, value, ctx)?;
+                    if ctx.is_object(&typ)? {
+                        let [array, index] = [array.compile(ctx)?, index.compile(ctx)?];
+                        let value = value.compile(ctx)?;
+                        format!("(call $set.{} {array} {index} {value})", ctx.layout(&typ)?)
+                    } else {
+                        let addr = ctx.element(array, index)?;
+                        let value = value.compile(ctx)?;
+                        ctx.store(&inner_typ, addr, value)?
+                    }
                 }
-                Expr::Field(expr, key) => {
-                    let typ = expr.infer(ctx)?;
+                Expr::Field(expr, key, _) => {
+                    let (typ, inner_typ) = Stmt::slot(name, value, ctx)?;
                     let Type::Dict(dict) = typ.clone() else {
                         return None;
                     };
-                    let inner_typ = dict.get(key)?.clone();
                     let offset = dict.get_index_of(key)? as i32 * BYTES;
-                    type_check!(inner_typ, value.infer(ctx)?, ctx)?;
-                    let addr = Box::new(offset_calc!(expr, offset, typ));
-                    Expr::Poke(addr, Box::new(value.clone())).compile(ctx)?
+                    if ctx.is_object(&typ)? {
+                        let [object, value] = [expr.compile(ctx)?, value.compile(ctx)?];
+                        let index = ctx.layout(&typ)?;
+                        let field = dict.get_index_of(key)?;
+                        format!("(struct.set $type.{index} {field} {object} {value})")
+                    } else {
+                        let addr = ctx.field(expr, offset)?;
+                        let value = value.compile(ctx)?;
+                        ctx.store(&inner_typ, addr, value)?
+                    }
                 }
                 _ => return None,
             },
-            Stmt::Try(expr, catch) => expr.compile(ctx).or(catch.compile(ctx))?,
-            Stmt::Import(funcs) => {
+            Stmt::Try(expr, catch, _) => {
+                let errors = ctx.errors.len();
+                if let Some(code) = expr.compile(ctx) {
+                    code
+                } else {
+                    let code = catch.compile(ctx)?;
+                    ctx.errors.truncate(errors);
+                    code
+                }
+            }
+            Stmt::Import(funcs, _) => {
                 self.infer(ctx)?;
                 let (name, _, ret_typ) = funcs.clone();
                 let function = ctx.function.get(&name)?.clone();
                 let sig = compile_args!(function, ctx);
                 let ret = compile_return!(ret_typ, ctx);
-                let code = format!("(import \"env\" \"{name}\" (func ${name} {sig} {ret}))");
+                let native = ctx.wrap_import(&name, &function)?;
+                let code = format!("(import \"env\" \"{name}\" (func ${native} {sig} {ret}))");
                 ctx.import.push(code);
                 String::new()
             }
-            Stmt::Return(Some(expr)) => {
-                format!("(return {})", expr.compile(ctx)?)
+            Stmt::Return(Some(expr), _) => {
+                let (typ, code) = (expr.infer(ctx)?, expr.compile(ctx)?);
+                format!("(return {})", ctx.epilogue(&typ, code)?)
             }
-            Stmt::Return(_) => "(return)".to_string(),
-            Stmt::Type(_, _) | Stmt::Module(_, _, _) | Stmt::Overload(_, (_, _), _) => {
+            Stmt::Return(_, _) => format!("(return {})", ctx.epilogue(&Type::Void, String::new())?),
+            // Code is generated for each instance at the call
+            Stmt::Generic(..) => {
+                self.infer(ctx)?;
                 String::new()
             }
-        })
+            Stmt::Type(..) | Stmt::Module(..) | Stmt::Overload(..) => String::new(),
+        };
+        if let Stmt::Let(_, name, _, _) = self {
+            ctx.unnarrow(name);
+        }
+        ctx.span = outer;
+        Some(code)
     }
 
     fn infer(&self, ctx: &mut Compiler) -> Option<Type> {
-        Some(match self {
-            Stmt::Expr(expr) => expr.infer(ctx)?,
-            Stmt::If(cond, then, r#else) => {
+        let outer = std::mem::replace(&mut ctx.span, self.span());
+        let typ = match self {
+            Stmt::Expr(expr, _) => expr.infer(ctx)?,
+            Stmt::If(cond, then, r#else, span) => {
+                ctx.span = cond.span();
                 type_check!(cond, Type::Bool, ctx)?;
+                ctx.span = *span;
                 if let Some(r#else) = r#else {
-                    type_check!(then, r#else, ctx)?
+                    let then_typ = ctx.narrow(cond, |ctx| then.infer(ctx)?.infer(ctx))?;
+                    let else_typ = r#else.infer(ctx)?.infer(ctx)?;
+                    if !then_typ.compare(&else_typ, ctx) {
+                        let [then_typ, else_typ] = [then_typ.format(), else_typ.format()];
+                        let msg = format!(
+                            "branches of if have different types {then_typ} and {else_typ}"
+                        );
+                        let label = format!("else branch is {else_typ}");
+                        let error = Diagnostic::error(msg, then.span()).label(r#else.span(), label);
+                        ctx.errors.push(error);
+                        return None;
+                    }
+                    then_typ
                 } else {
-                    then.infer(ctx)?
+                    ctx.narrow(cond, |ctx| then.infer(ctx))?
                 }
             }
-            Stmt::While(cond, body) => {
+            // Variables that the patterns bind are only in scope of the match
+            Stmt::Match(expr, arms, _) => {
+                Stmt::match_table(expr, arms, ctx)?;
+                let scope = ctx.variable.clone();
+                let mut result = None;
+                for (pattern, body) in arms {
+                    if let Some(pattern) = pattern {
+                        ctx.span = pattern.span();
+                        ctx.bind(pattern)?;
+                    }
+                    ctx.span = body.span();
+                    match &result {
+                        None => result = Some(body.infer(ctx)?),
+                        Some(typ) => _ = type_check!(typ, body, ctx)?,
+                    }
+                }
+                ctx.variable = scope;
+                result?
+            }
+            Stmt::While(cond, body, span) => {
+                ctx.span = cond.span();
                 type_check!(cond, Type::Bool, ctx)?;
+                ctx.span = *span;
                 let in_while = ctx.in_while;
                 ctx.in_while = true;
-                body.infer(ctx)?;
+                let body = ctx.narrow(cond, |ctx| body.infer(ctx));
                 ctx.in_while = in_while;
+                body?;
                 Type::Void
             }
-            Stmt::Break | Stmt::Next => {
+            Stmt::Break(_) | Stmt::Next(_) => {
                 if !ctx.in_while {
-                    ctx.error = Some("next statement outside of loop".to_string());
+                    ctx.report("next statement outside of loop");
                     return None;
                 }
                 Type::Void
             }
-            Stmt::Let(scope, name, value) => {
+            Stmt::Let(scope, name, value, _) => {
                 match name {
-                    Expr::Variable(name) => match scope {
+                    Expr::Variable(name, _) => match scope {
                         Scope::Local => {
                             if !ctx.argument.contains_key(name) {
-                                let value_type = value.infer(ctx)?;
+                                let Some(value_type) = value.infer(ctx) else {
+                                    ctx.poisoned.insert(name.to_string());
+                                    return None;
+                                };
                                 if let Some(exist_val) = ctx.clone().variable.get(name) {
                                     type_check!(exist_val, value_type, ctx)?;
+                                    // Variable may be null after it's assigned a possibly null value
+                                    if let Type::Nullable(_) = value_type.infer(ctx)? {
+                                        let typ = exist_val.clone().nullable();
+                                        ctx.variable.insert(name.to_string(), typ);
+                                    }
                                 } else {
                                     ctx.variable.insert(name.to_string(), value_type);
                                 }
                             } else {
-                                let msg = format!("can't reassign value to argument");
-                                ctx.error = Some(msg);
+                                ctx.report("can't reassign value to argument");
                                 return None;
                             }
                         }
                         Scope::Global => {
-                            let value_type = value.infer(ctx)?;
+                            let Some(value_type) = value.infer(ctx) else {
+                                ctx.poisoned.insert(name.to_string());
+                                return None;
+                            };
                             if let Some(exist_val) = ctx.clone().global.get(name) {
                                 type_check!(exist_val, value_type, ctx)?;
                             } else {
@@ -331,16 +504,22 @@ impl Node for Stmt {
                             }
                         }
                     },
-                    Expr::Call(name, args) => {
+                    Expr::Call(name, args, _) => {
                         let var_ctx = ctx.variable.clone();
                         let arg_ctx = ctx.argument.clone();
                         ctx.variable.clear();
                         ctx.argument.clear();
-                        check_args!(args, ctx);
-                        let frame = Function {
-                            returns: value.infer(ctx)?,
-                            variables: ctx.variable.clone(),
-                            arguments: ctx.argument.clone(),
+                        let narrowed = std::mem::take(&mut ctx.narrowed);
+                        let frame = ctx.implicit(self, args, |ctx, args| {
+                            check_args!(args, ctx);
+                            ctx.recursive(name, value)
+                        });
+                        ctx.variable = var_ctx;
+                        ctx.argument = arg_ctx;
+                        ctx.narrowed = narrowed;
+                        let Some(frame) = frame else {
+                            ctx.poisoned.insert(name.to_owned());
+                            return None;
                         };
                         if let Scope::Global = scope {
                             &mut ctx.export
@@ -348,47 +527,86 @@ impl Node for Stmt {
                             &mut ctx.function
                         }
                         .insert(name.to_owned(), frame);
-                        ctx.variable = var_ctx;
-                        ctx.argument = arg_ctx;
                     }
-                    Expr::Operator(oper) => match *oper.clone() {
-                        Op::Cast(Expr::Call(name, args), ret) => {
+                    Expr::Operator(oper, _) => match *oper.clone() {
+                        Op::Cast(Expr::Call(name, args, _), ret) => {
                             let var_ctx = ctx.variable.clone();
                             let arg_ctx = ctx.argument.clone();
                             ctx.variable.clear();
                             ctx.argument.clear();
-                            check_args!(args.clone(), ctx);
-                            ctx.function.insert(
-                                name.to_owned(),
-                                Function {
+                            let narrowed = std::mem::take(&mut ctx.narrowed);
+                            let frame = ctx.implicit(self, &args, |ctx, args| {
+                                check_args!(args, ctx);
+                                ctx.function.insert(
+                                    name.to_owned(),
+                                    Function {
+                                        variables: ctx.variable.clone(),
+                                        arguments: ctx.argument.clone(),
+                                        returns: ret.clone(),
+                                    },
+                                );
+                                let body = value.infer(ctx)?;
+                                ctx.tolerate(|ctx| type_check!(body, ret, ctx));
+                                ctx.expect_non_null(&ret, &body)?;
+                                Some(Function {
                                     variables: ctx.variable.clone(),
                                     arguments: ctx.argument.clone(),
                                     returns: ret.clone(),
-                                },
-                            );
-                            type_check!(value.infer(ctx)?, ret, ctx);
+                                })
+                            });
                             ctx.variable = var_ctx;
                             ctx.argument = arg_ctx;
+                            ctx.narrowed = narrowed;
+                            ctx.function.insert(name.to_owned(), frame?);
                         }
                         _ => return None,
                     },
-                    _ => {
-                        value.infer(ctx);
+                    Expr::Index(..) | Expr::Field(..) => {
+                        Stmt::slot(name, value, ctx)?;
                     }
+                    _ => return None,
                 }
+                // Fields checked not to be null may be changed by the assignment
+                ctx.unnarrow(name);
                 Type::Void
             }
-            Stmt::Type(name, value) => {
+            // Body is checked with the type parameters that any types can be given
+            Stmt::Generic(scope, params, target, value, span) => {
+                if let Scope::Global = scope {
+                    ctx.report("can't export generic function, as its types depend on the call");
+                    return None;
+                }
+                let name = function_name(target)?.clone();
+                let stmt = Stmt::Let(*scope, target.clone(), value.clone(), *span);
+                ctx.template.insert(name, (params.clone(), stmt.clone()));
+                let types = params.iter().cloned().map(Type::Param).collect();
+                ctx.with_params(params, types, |ctx| stmt.infer(ctx))?
+            }
+            Stmt::Type(name, params, value, _) => {
                 ctx.alias.insert(name.to_string(), value.clone());
+                if params.is_empty() {
+                    ctx.generic.swap_remove(name);
+                } else {
+                    ctx.generic.insert(name.to_string(), params.clone());
+                }
                 Type::Void
             }
-            Stmt::Module(name, args, expr) => {
+            Stmt::Module(name, args, expr, _) => {
                 let value = (args.clone(), expr.clone());
                 ctx.module.insert(name.to_owned(), value);
                 Type::Void
             }
-            Stmt::Try(expr, catch) => expr.infer(ctx).or(catch.infer(ctx))?,
-            Stmt::Import(function) => {
+            Stmt::Try(expr, catch, _) => {
+                let errors = ctx.errors.len();
+                if let Some(typ) = expr.infer(ctx) {
+                    typ
+                } else {
+                    let typ = catch.infer(ctx)?;
+                    ctx.errors.truncate(errors);
+                    typ
+                }
+            }
+            Stmt::Import(function, _) => {
                 let (fn_name, args, ret_typ) = function;
                 ctx.function.insert(
                     fn_name.clone(),
@@ -406,16 +624,181 @@ impl Node for Stmt {
                 );
                 Type::Void
             }
-            Stmt::Overload(id, (arg1, arg2), name) => {
+            Stmt::Overload(id, (arg1, arg2), name, _) => {
                 ctx.overload
                     .insert(name.clone(), (*id, (arg1.clone(), arg2.clone())));
                 Type::Void
             }
-            Stmt::Return(Some(value)) => {
+            Stmt::Return(Some(value), _) => {
                 value.infer(ctx)?;
                 Type::Void
             }
-            Stmt::Return(_) => Type::Void,
-        })
+            Stmt::Return(_, _) => Type::Void,
+        };
+        ctx.span = outer;
+        Some(typ)
+    }
+}
+
+impl Stmt {
+    /// Rest of `let` statement after the keyword, that's assignment or compound one
+    fn parse_let(p: &mut Parser, start: usize, scope: Scope) -> Option<Stmt> {
+        if let Some(Token::Ident(name)) = p.peek()
+            && BUILTIN.contains(&name.as_str())
+            && matches!(p.peek_at(1), Some(Token::Punct('(') | Token::Operator("<")))
+        {
+            let msg = format!("can't define function `{name}`, as it's built-in");
+            return p.error(msg, p.span());
+        }
+        if let Some(Token::Ident(name)) = p.peek().cloned()
+            && p.peek_at(1) == Some(&Token::Operator("<"))
+        {
+            return Stmt::parse_generic(p, start, scope, name);
+        }
+        let name = Expr::parse(p)?;
+        if p.eat(Token::Operator("=")).is_some() {
+            let value = Expr::parse(p)?;
+            return Some(Stmt::Let(scope, name, value, p.span_from(start)));
+        }
+        let (span, Expr::Operator(source, _)) = (p.span_from(start), name) else {
+            return p.expected("`=`");
+        };
+        macro_rules! assign_with {
+            ($op: ident) => {
+                if let Op::$op(name, value) = *source {
+                    let oper = Box::new(Op::$op(name.clone(), value));
+                    let value = Expr::Operator(oper, span);
+                    return Some(Stmt::Let(scope, name, value, span));
+                }
+            };
+        }
+        assign_with!(Add);
+        assign_with!(Sub);
+        assign_with!(Mul);
+        assign_with!(Div);
+        assign_with!(Mod);
+        p.expected("`=`")
+    }
+
+    /// Generic function definition after `let`, whose type parameters follow the name
+    fn parse_generic(p: &mut Parser, start: usize, scope: Scope, name: String) -> Option<Stmt> {
+        let name_start = p.index;
+        p.advance();
+        let params = p.angle(Parser::ident)?;
+        let open = p.expect(Token::Punct('('))?;
+        let args = p.list(open, ')', Expr::parse)?;
+        let mut target = Expr::Call(name, args, p.span_from(name_start));
+        if p.eat(Token::Operator(":")).is_some() {
+            let oper = Op::Cast(target, Type::parse(p)?);
+            target = Expr::Operator(Box::new(oper), p.span_from(name_start));
+        }
+        p.expect(Token::Operator("="))?;
+        let value = Expr::parse(p)?;
+        Some(Stmt::Generic(
+            scope,
+            params,
+            target,
+            value,
+            p.span_from(start),
+        ))
+    }
+
+    /// Types of the object and of its element or field that's assigned, where the value must fit
+    fn slot(target: &Expr, value: &Expr, ctx: &mut Compiler) -> Option<(Type, Type)> {
+        let (object, access) = match target {
+            Expr::Index(array, _, _) => (array, "index access to"),
+            Expr::Field(expr, _, _) => (expr, "field access to"),
+            _ => return None,
+        };
+        // Target is inferred as an expression first to report where it can't be accessed
+        target.infer(ctx)?;
+        let typ = object.infer(ctx)?;
+        let typ = ctx.deref(typ, access)?;
+        let inner_typ = match (target, typ.infer(ctx)?) {
+            (Expr::Index(..), Type::Array(inner_typ)) => *inner_typ,
+            (Expr::Field(_, key, _), Type::Dict(dict)) => dict.get(key)?.clone(),
+            _ => return None,
+        };
+        ctx.expect_value(&inner_typ, value)?;
+        Some((typ, inner_typ))
+    }
+
+    /// Index of the arm that each variant of the enum goes to, where all the variants
+    /// have to be matched by the patterns or `_`
+    fn match_table(
+        expr: &Expr,
+        arms: &[(Option<Expr>, Expr)],
+        ctx: &mut Compiler,
+    ) -> Option<Vec<usize>> {
+        let outer = ctx.span;
+        ctx.span = expr.span();
+        let typ = expr.infer(ctx)?.infer(ctx)?;
+        let typ = ctx.deref(typ, "match")?;
+        let name = typ.restore_alias(ctx).format();
+        let Type::Enum(variants) = typ.clone() else {
+            ctx.report(format!("can't match {name}, only enums can be matched"));
+            return None;
+        };
+        let (mut table, mut default) = (vec![None; variants.len()], None);
+        for (index, (pattern, body)) in arms.iter().enumerate() {
+            let Some(pattern) = pattern else {
+                ctx.span = body.span();
+                if default.replace(index).is_some() {
+                    ctx.report("default arm `_` is given more than once");
+                    return None;
+                }
+                continue;
+            };
+            ctx.span = pattern.span();
+            let Expr::Literal(Value::Enum(pattern, key, args), _) = pattern else {
+                ctx.report("pattern of match should be a variant like `E#a`, or `_`");
+                return None;
+            };
+            let (pattern, variant, fields) = ctx.fields_of(pattern, key)?;
+            type_check!(pattern, typ, ctx)?;
+            ctx.arity(key, fields.len(), args.len())?;
+            if let Some(arg) = args.iter().find(|arg| !matches!(arg, Expr::Variable(..))) {
+                ctx.span = arg.span();
+                ctx.report("field of the pattern should be a name to bind, or `_`");
+                return None;
+            }
+            if table[variant].replace(index).is_some() {
+                ctx.report(format!("variant `{key}` is matched more than once"));
+                return None;
+            }
+        }
+        ctx.span = outer;
+        let missing: Vec<_> = variants
+            .iter()
+            .zip(&table)
+            .filter(|(_, arm)| arm.is_none())
+            .map(|((key, _), _)| format!("`{key}`"))
+            .collect();
+        if default.is_none() && !missing.is_empty() {
+            let msg = format!("match over {name} doesn't cover {}", missing.join(", "));
+            let note = "add arms for them, or `_` to match the rest";
+            ctx.errors.push(Diagnostic::error(msg, ctx.span).note(note));
+            return None;
+        }
+        table.into_iter().map(|arm| arm.or(default)).collect()
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expr(_, span)
+            | Stmt::Let(_, _, _, span)
+            | Stmt::If(_, _, _, span)
+            | Stmt::Match(_, _, span)
+            | Stmt::While(_, _, span)
+            | Stmt::Generic(_, _, _, _, span)
+            | Stmt::Type(_, _, _, span)
+            | Stmt::Try(_, _, span)
+            | Stmt::Module(_, _, _, span)
+            | Stmt::Overload(_, _, _, span)
+            | Stmt::Import(_, span)
+            | Stmt::Return(_, span)
+            | Stmt::Break(span)
+            | Stmt::Next(span) => *span,
+        }
     }
 }
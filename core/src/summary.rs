@@ -0,0 +1,115 @@
+use crate::*;
+use serde::Serialize;
+
+/// Inferred signatures of the program, where types are written in the source code syntax
+#[derive(Clone, Debug, Serialize)]
+pub struct Summary {
+    pub functions: IndexMap<String, FunctionSummary>,
+    /// Functions generated for the generic ones, by the name with the type arguments
+    pub instances: IndexMap<String, FunctionSummary>,
+    /// Functions exported by `pub let`
+    pub exports: IndexMap<String, FunctionSummary>,
+    pub overloads: IndexMap<String, OverloadSummary>,
+    pub variables: IndexMap<String, String>,
+    pub globals: IndexMap<String, String>,
+    pub aliases: IndexMap<String, String>,
+    /// Macro names with their arguments
+    pub macros: IndexMap<String, Vec<String>>,
+    pub result: String,
+}
+
+/// Signature whose return type is `_` if it's only found at each call
+#[derive(Clone, Debug, Serialize)]
+pub struct FunctionSummary {
+    pub arguments: IndexMap<String, String>,
+    pub returns: String,
+}
+
+/// Operator overload, where `rhs` is missing for unary negation
+#[derive(Clone, Debug, Serialize)]
+pub struct OverloadSummary {
+    pub op: String,
+    pub lhs: String,
+    pub rhs: Option<String>,
+}
+
+impl Compiler {
+    pub fn summary(&self) -> Summary {
+        let typ = |typ: &Type| typ.restore_alias(self).format();
+        let types = |types: &IndexMap<String, Type>| {
+            types
+                .iter()
+                .map(|(name, t)| (name.clone(), typ(t)))
+                .collect()
+        };
+        let signature = |name: &str, func: &Function| FunctionSummary {
+            arguments: types(&func.arguments),
+            returns: match (&func.returns, self.template.get(name)) {
+                (Type::Var(var), Some((params, _))) if !params.contains(var) => "_".to_string(),
+                (returns, _) => typ(returns),
+            },
+        };
+        // Instance is named by the index of its key, which is the name with the type arguments
+        let instances: IndexMap<_, _> = self
+            .instances
+            .iter()
+            .enumerate()
+            .filter_map(|(index, key)| {
+                let (name, _) = key.split_once('<')?;
+                let func = self.function.get(&format!("{name}.{index}"))?;
+                Some((
+                    format!("{name}.{index}"),
+                    (key.clone(), signature(name, func)),
+                ))
+            })
+            .collect();
+        let functions = |funcs: &IndexMap<String, Function>| {
+            funcs
+                .iter()
+                .filter(|(name, _)| !instances.contains_key(*name))
+                .map(|(name, func)| (name.clone(), signature(name, func)))
+                .collect()
+        };
+        let table = Op::overload_id_table();
+        let overloads = self.overload.iter().map(|(name, (id, (lhs, rhs)))| {
+            let op = table
+                .iter()
+                .find(|&(_, n)| n == id)
+                .map(|(op, _)| op.as_str());
+            let overload = match op.unwrap_or_default() {
+                "neg" => OverloadSummary {
+                    op: "-".to_string(),
+                    lhs: lhs.format(),
+                    rhs: None,
+                },
+                op => OverloadSummary {
+                    op: op.to_string(),
+                    lhs: lhs.format(),
+                    rhs: Some(rhs.format()),
+                },
+            };
+            (name.clone(), overload)
+        });
+        let aliases = self.alias.iter();
+        Summary {
+            functions: functions(&self.function),
+            exports: functions(&self.export),
+            instances: instances.values().cloned().collect(),
+            overloads: overloads.collect(),
+            variables: types(&self.variable),
+            globals: types(&self.global),
+            aliases: aliases
+                .map(|(name, t)| match self.generic.get(name) {
+                    Some(params) => (format!("{name}<{}>", params.join(", ")), t.format()),
+                    None => (name.clone(), t.format()),
+                })
+                .collect(),
+            macros: self
+                .module
+                .iter()
+                .map(|(name, (args, _))| (name.clone(), args.clone()))
+                .collect(),
+            result: typ(&self.result),
+        }
+    }
+}
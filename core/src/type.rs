@@ -1,78 +1,66 @@
 use crate::*;
 
 pub type Dict = IndexMap<String, Type>;
-pub type Enum = IndexSet<String>;
+/// Variants of the enum with the types of the fields that they carry
+pub type Enum = IndexMap<String, Vec<Type>>;
 #[derive(Clone, Debug)]
 pub enum Type {
     Integer,
     Number,
+    /// 64-bit integer
+    Long,
+    /// 64-bit floating point number
+    Double,
+    /// 32-bit integer that operators treat as unsigned
+    Unsigned,
     Bool,
     String,
     Array(Box<Type>),
     Dict(Dict),
     Enum(Enum),
+    /// Closure that takes the arguments and returns the value, written `(int, int) -> bool`
+    Function(Vec<Type>, Box<Type>),
     Alias(String),
+    /// Generic alias given the type arguments, written `Pair<int, str>`
+    Generic(String, Vec<Type>),
+    /// Type parameter of the generic function, which is opaque until the function is called
+    Param(String),
+    /// Type of the function argument without annotation, that its uses bind, written `'a`
+    Var(String),
+    /// Pointer type that may be null, written `T?`
+    Nullable(Box<Type>),
     Any,
     Void,
 }
 
 impl Node for Type {
-    fn parse(source: &str) -> Option<Type> {
-        match source.trim() {
-            "int" => Some(Type::Integer),
-            "num" => Some(Type::Number),
-            "bool" => Some(Type::Bool),
-            "str" => Some(Type::String),
-            "void" => Some(Type::Void),
-            "any" => Some(Type::Any),
-            source => {
-                let mut source = source.trim().to_owned();
-                if source.starts_with("[") && source.ends_with("]") {
-                    let source = source.get(1..source.len() - 1)?.trim();
-                    Some(Type::Array(Box::new(Type::parse(source)?)))
-                } else if source.starts_with("@{") && source.ends_with("}") {
-                    let source = source.get(2..source.len() - 1)?.trim();
-                    let mut result = IndexMap::new();
-                    for line in tokenize(source, &[","], false, true, false)? {
-                        let (name, value) = line.split_once(":")?;
-                        let mut name = name.trim().to_owned();
-                        if !is_identifier(&mut name) {
-                            return None;
-                        };
-                        result.insert(name, Type::parse(value)?);
-                    }
-                    Some(Type::Dict(result))
-                } else if source.starts_with("(") && source.ends_with(")") {
-                    let source = source.get(1..source.len() - 1)?.trim();
-                    let tokens = tokenize(source, &["|"], false, true, false)?;
-                    let mut result: IndexSet<String> = IndexSet::new();
-                    for key in tokens {
-                        let mut value = key.trim().to_owned();
-                        if !is_identifier(&mut value) {
-                            return None;
-                        };
-                        result.insert(value);
-                    }
-                    Some(Type::Enum(result))
-                } else if is_identifier(&mut source) {
-                    Some(Type::Alias(source.to_string()))
-                } else {
-                    None
-                }
-            }
+    fn parse(p: &mut Parser) -> Option<Type> {
+        let typ = Type::parse_base(p)?;
+        if p.eat(Token::Operator("?")).is_some() {
+            return Some(Type::Nullable(Box::new(typ)));
         }
+        Some(typ)
     }
 
     fn compile(&self, ctx: &mut Compiler) -> Option<String> {
         Some(match self.infer(ctx)? {
             Type::Number => "f32".to_string(),
+            Type::Long => "i64".to_string(),
+            Type::Double => "f64".to_string(),
+            typ @ (Type::Array(_) | Type::Dict(_)) if ctx.gc == Gc::Wasm => ctx.reference(&typ)?,
             Type::Integer
+            | Type::Unsigned
             | Type::Bool
             | Type::String
             | Type::Array(_)
             | Type::Dict(_)
-            | Type::Enum(_) => "i32".to_string(),
-            Type::Any => ctx.alias.get(&Type::Any.format())?.clone().compile(ctx)?,
+            | Type::Enum(_)
+            | Type::Function(..) => "i32".to_string(),
+            Type::Nullable(typ) => typ.compile(ctx)?,
+            Type::Any => {
+                ctx.report("can't compile `any` itself, as it takes a different type at each call");
+                return None;
+            }
             _ => return None,
         })
     }
@@ -83,8 +71,112 @@ impl Node for Type {
 }
 
 impl Type {
+    /// Type without the suffix `?`
+    fn parse_base(p: &mut Parser) -> Option<Type> {
+        match p.peek().cloned() {
+            Some(Token::Ident(name)) => {
+                p.advance();
+                Some(match name.as_str() {
+                    "int" => Type::Integer,
+                    "num" => Type::Number,
+                    "long" => Type::Long,
+                    "double" => Type::Double,
+                    "uint" => Type::Unsigned,
+                    "bool" => Type::Bool,
+                    "str" => Type::String,
+                    "void" => Type::Void,
+                    "any" => Type::Any,
+                    _ if p.peek() == Some(&Token::Operator("<")) => {
+                        Type::Generic(name, p.angle(Type::parse)?)
+                    }
+                    _ => Type::Alias(name),
+                })
+            }
+            Some(Token::Punct('[')) => {
+                let open = p.expect(Token::Punct('['))?;
+                let typ = Type::parse(p)?;
+                p.close(open, ']')?;
+                Some(Type::Array(Box::new(typ)))
+            }
+            Some(Token::Punct('@')) => {
+                p.advance();
+                let open = p.expect(Token::Punct('{'))?;
+                let fields = p.list(open, '}', |p| {
+                    let name = p.ident()?;
+                    p.expect(Token::Operator(":"))?;
+                    Some((name, Type::parse(p)?))
+                })?;
+                Some(Type::Dict(fields.into_iter().collect()))
+            }
+            // Type grouped in the parentheses like `((int) -> int)?`, as variants are identifiers
+            Some(Token::Punct('(')) if p.peek_at(1) == Some(&Token::Punct('(')) => {
+                let open = p.expect(Token::Punct('('))?;
+                let typ = Type::parse(p)?;
+                p.close(open, ')')?;
+                Some(typ)
+            }
+            // Function type `(int) -> int`, or `fn(int) -> int` as the lambda is written
+            Some(Token::Keyword("fn")) => {
+                p.advance();
+                Type::parse_signature(p)
+            }
+            Some(Token::Punct('(')) if Type::is_signature(p) => Type::parse_signature(p),
+            Some(Token::Punct('(')) => {
+                let open = p.expect(Token::Punct('('))?;
+                let mut result = IndexMap::new();
+                loop {
+                    let name = p.ident()?;
+                    let fields = match p.eat(Token::Punct('(')) {
+                        Some(open) => p.list(open, ')', Type::parse)?,
+                        None => vec![],
+                    };
+                    result.insert(name, fields);
+                    if p.eat(Token::Operator("|")).is_none() {
+                        break;
+                    }
+                }
+                p.close(open, ')')?;
+                Some(Type::Enum(result))
+            }
+            _ => p.expected("type"),
+        }
+    }
+
+    /// Arguments in the parentheses and the return type after `->`
+    fn parse_signature(p: &mut Parser) -> Option<Type> {
+        let open = p.expect(Token::Punct('('))?;
+        let args = p.list(open, ')', Type::parse)?;
+        p.expect(Token::Operator("->"))?;
+        Some(Type::Function(args, Box::new(Type::parse(p)?)))
+    }
+
+    /// Whether the parentheses are followed by `->`, that's the arguments of a function type
+    /// instead of an enum
+    fn is_signature(p: &Parser) -> bool {
+        let (mut depth, mut ahead) = (0, 0);
+        loop {
+            match p.peek_at(ahead) {
+                Some(Token::Punct('(')) => depth += 1,
+                Some(Token::Punct(')')) if depth == 1 => {
+                    return p.peek_at(ahead + 1) == Some(&Token::Operator("->"));
+                }
+                Some(Token::Punct(')')) => depth -= 1,
+                Some(_) => {}
+                None => return false,
+            }
+            ahead += 1;
+        }
+    }
+
     pub fn solve_alias(&self, ctx: &mut Compiler, xpct: Vec<Type>) -> Option<Type> {
-        for x in &xpct {
+        // Generic one is found by its solved arguments instead
+        let is_generic = match self {
+            Type::Generic(..) => true,
+            Type::Alias(name) => ctx.params.contains_key(name),
+            Type::Var(_) => true,
+            _ => false,
+        };
+        for x in xpct.iter().filter(|_| !is_generic) {
             let result = self.restore_alias(ctx);
             if x.restore_alias(ctx) == result {
                 return Some(result);
@@ -92,13 +184,52 @@ impl Type {
         }
         let xpcts = [xpct.clone(), vec![self.clone()]].concat();
         match self {
+            Type::Alias(name) if ctx.params.contains_key(name) => {
+                ctx.params[name].clone().solve_alias(ctx, xpct)
+            }
+            // Variable bound through the others back to itself stays as it is
+            Type::Var(name) => match ctx.params.get(name) {
+                Some(typ) if !xpct.contains(self) => typ.clone().solve_alias(ctx, xpcts),
+                _ => Some(self.clone()),
+            },
             Type::Alias(name) => {
                 let Some(typ) = ctx.alias.get(name).cloned() else {
-                    ctx.error = Some(format!("undefined type alias `{name}`"));
+                    ctx.report(format!("undefined type alias `{name}`"));
                     return None;
                 };
+                if let Some(params) = ctx.generic.get(name) {
+                    let count = params.len();
+                    ctx.report(format!("type alias `{name}` needs {count} type arguments"));
+                    return None;
+                }
                 typ.solve_alias(ctx, xpct.clone())
             }
+            // Arguments are solved first, so that the recursive use of the alias is found
+            Type::Generic(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|typ| typ.solve_alias(ctx, xpcts.clone()))
+                    .collect::<Option<Vec<_>>>()?;
+                let generic = Type::Generic(name.clone(), args.clone());
+                if xpct.contains(&generic) {
+                    return Some(generic);
+                }
+                let (Some(typ), Some(params)) =
+                    (ctx.alias.get(name).cloned(), ctx.generic.get(name).cloned())
+                else {
+                    ctx.report(format!("undefined generic type alias `{name}`"));
+                    return None;
+                };
+                if params.len() != args.len() {
+                    let [count, given] = [params.len(), args.len()];
+                    ctx.report(format!(
+                        "type alias `{name}` takes {count} type arguments, but {given} are given"
+                    ));
+                    return None;
+                }
+                let xpct = [xpct, vec![generic]].concat();
+                ctx.with_params(&params, args, |ctx| typ.solve_alias(ctx, xpct))
+            }
             Type::Array(typ) => Some(Type::Array(Box::new(typ.solve_alias(ctx, xpcts)?))),
             Type::Dict(dict) => {
                 let mut result = IndexMap::new();
@@ -108,70 +239,130 @@ impl Type {
                 }
                 Some(Type::Dict(result))
             }
+            Type::Enum(variants) => {
+                let mut result = IndexMap::new();
+                for (name, fields) in variants {
+                    let fields = fields
+                        .iter()
+                        .map(|typ| typ.solve_alias(ctx, xpcts.clone()))
+                        .collect::<Option<_>>()?;
+                    result.insert(name.clone(), fields);
+                }
+                Some(Type::Enum(result))
+            }
+            Type::Function(args, ret) => {
+                let args = args
+                    .iter()
+                    .map(|typ| typ.solve_alias(ctx, xpcts.clone()))
+                    .collect::<Option<_>>()?;
+                let ret = ret.solve_alias(ctx, xpcts)?;
+                Some(Type::Function(args, Box::new(ret)))
+            }
+            Type::Nullable(typ) => Some(typ.solve_alias(ctx, xpct)?.nullable()),
             _ => Some(self.clone()),
         }
     }
 
     pub fn restore_alias(&self, ctx: &Compiler) -> Type {
         let typ = match self {
+            // Alias of the pointer type doesn't include null
+            Type::Nullable(typ) => return typ.restore_alias(ctx).nullable(),
+            // It's equal to any type, so it's no alias's value
+            Type::Any => return Type::Any,
             Type::Array(typ) => Type::Array(Box::new(typ.restore_alias(ctx))),
             Type::Dict(dict) => Type::Dict(
                 dict.iter()
                     .map(|(key, typ)| (key.clone(), (typ.restore_alias(ctx))))
                     .collect(),
             ),
+            Type::Enum(variants) => Type::Enum(
+                variants
+                    .iter()
+                    .map(|(key, fields)| {
+                        let fields = fields.iter().map(|typ| typ.restore_alias(ctx));
+                        (key.clone(), fields.collect())
+                    })
+                    .collect(),
+            ),
+            Type::Function(args, ret) => Type::Function(
+                args.iter().map(|typ| typ.restore_alias(ctx)).collect(),
+                Box::new(ret.restore_alias(ctx)),
+            ),
+            Type::Generic(name, args) => Type::Generic(
+                name.clone(),
+                args.iter().map(|typ| typ.restore_alias(ctx)).collect(),
+            ),
             _ => self.clone(),
         };
-        let mut aliases = ctx.alias.iter();
+        let mut aliases = ctx
+            .alias
+            .iter()
+            .filter(|(k, _)| !ctx.generic.contains_key(*k));
         if let Some((alias, _)) = aliases.find(|(_, v)| **v == typ) {
-            if *alias != Type::Any.format() {
-                Type::Alias(alias.clone())
-            } else {
-                typ
-            }
+            Type::Alias(alias.clone())
         } else {
-            typ
+            ctx.instance_of(&typ).unwrap_or(typ)
         }
     }
 
     pub fn compare(&self, other: &Self, ctx: &mut Compiler) -> bool {
+        match (self, other) {
+            (Type::Var(_), _) | (_, Type::Var(_)) => self.equals(other, ctx),
+            // Null is checked where the value is accessed or passed, not in the type equality
+            (Type::Nullable(a), b) | (b, Type::Nullable(a)) => a.compare(b, ctx),
+            _ => self.equals(other, ctx),
+        }
+    }
+
+    /// Equality where the fields, elements and signatures allow null only as they're declared
+    fn equals(&self, other: &Self, ctx: &mut Compiler) -> bool {
         match (self, other) {
             (Type::Integer, Type::Integer) => true,
             (Type::Number, Type::Number) => true,
+            (Type::Long, Type::Long) => true,
+            (Type::Double, Type::Double) => true,
+            (Type::Unsigned, Type::Unsigned) => true,
             (Type::Bool, Type::Bool) => true,
             (Type::String, Type::String) => true,
             (Type::Void, Type::Void) => true,
-            (Type::Any, Type::Any) => true,
-            (Type::Any, typ) | (typ, Type::Any) => {
-                if let Some(any) = ctx.alias.get(&Type::Any.format()) {
-                    typ == any
-                } else {
-                    ctx.alias.insert(Type::Any.format(), typ.clone());
-                    true
-                }
-            }
+            (Type::Var(name), typ) | (typ, Type::Var(name)) => ctx.bind_var(name, typ),
+            (Type::Nullable(a), Type::Nullable(b)) => a.equals(b, ctx),
+            // It's left where the call gives no type to it, like an empty array
+            (Type::Any, _) | (_, Type::Any) => true,
             (Type::Dict(a), Type::Dict(b)) => {
-                a.iter().zip(b).all(|((_, a), (_, b))| a.compare(b, ctx))
+                a.iter().zip(b).all(|((_, a), (_, b))| a.equals(b, ctx))
             }
             (Type::Enum(a), Type::Enum(b)) => a == b,
-            (Type::Array(a), Type::Array(b)) => a.clone().compare(b, ctx),
+            (Type::Function(a, r), Type::Function(b, s)) => {
+                a.len() == b.len()
+                    && a.iter().zip(b).all(|(a, b)| a.equals(b, ctx))
+                    && r.equals(s, ctx)
+            }
+            (Type::Array(a), Type::Array(b)) => a.equals(b, ctx),
             (Type::Alias(a), Type::Alias(b)) => a == b,
+            (Type::Generic(a, x), Type::Generic(b, y)) => {
+                a == b && x.len() == y.len() && x.iter().zip(y).all(|(x, y)| x.equals(y, ctx))
+            }
+            (Type::Param(a), Type::Param(b)) => a == b,
             _ => false,
         }
     }
 
-    pub fn polymorphism(&self, ctx: &mut Compiler) -> Type {
+    /// Type where `any` is replaced with the type, that the signature takes at the call
+    pub fn instantiate(&self, typ: &Type) -> Type {
         match self {
-            Type::Any => ctx
-                .alias
-                .swap_remove(&Type::Any.format())
-                .unwrap_or(Type::Any),
+            Type::Any => typ.clone(),
             Type::Dict(dict) => Type::Dict(
                 dict.iter()
-                    .map(|(key, typ)| (key.clone(), typ.polymorphism(ctx)))
+                    .map(|(key, field)| (key.clone(), field.instantiate(typ)))
                     .collect::<IndexMap<String, Type>>(),
             ),
-            Type::Array(typ) => Type::Array(Box::new(typ.polymorphism(ctx))),
+            Type::Array(element) => Type::Array(Box::new(element.instantiate(typ))),
+            Type::Function(args, ret) => Type::Function(
+                args.iter().map(|arg| arg.instantiate(typ)).collect(),
+                Box::new(ret.instantiate(typ)),
+            ),
+            Type::Nullable(inner) => inner.instantiate(typ).nullable(),
             primitive => primitive.clone(),
         }
     }
@@ -180,6 +371,9 @@ impl Type {
         match self {
             Type::Integer => "int".to_string(),
             Type::Number => "num".to_string(),
+            Type::Long => "long".to_string(),
+            Type::Double => "double".to_string(),
+            Type::Unsigned => "uint".to_string(),
             Type::Bool => "bool".to_string(),
             Type::String => "str".to_string(),
             Type::Void => "void".to_string(),
@@ -191,12 +385,89 @@ impl Type {
                     .collect::<Vec<_>>()
                     .join(", ")
             ),
-            Type::Enum(e) => format!(
+            Type::Enum(variants) => format!(
                 "( {} )",
-                e.iter().cloned().collect::<Vec<String>>().join(" | ")
+                variants
+                    .iter()
+                    .map(|(key, fields)| match fields.as_slice() {
+                        [] => key.clone(),
+                        fields => {
+                            let fields: Vec<_> = fields.iter().map(Type::format).collect();
+                            format!("{key}({})", fields.join(", "))
+                        }
+                    })
+                    .collect::<Vec<String>>()
+                    .join(" | ")
             ),
+            Type::Function(args, ret) => {
+                let args: Vec<_> = args.iter().map(Type::format).collect();
+                format!("({}) -> {}", args.join(", "), ret.format())
+            }
             Type::Array(typ) => format!("[{}]", typ.format()),
-            Type::Alias(name) => name.to_string(),
+            Type::Alias(name) | Type::Param(name) | Type::Var(name) => name.to_string(),
+            Type::Generic(name, args) => {
+                let args: Vec<_> = args.iter().map(Type::format).collect();
+                format!("{name}<{}>", args.join(", "))
+            }
+            Type::Nullable(typ) => format!("{}?", typ.format()),
+        }
+    }
+
+    /// Whether it's one of the types that arithmetic operators take
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Type::Integer | Type::Number | Type::Long | Type::Double | Type::Unsigned
+        )
+    }
+
+    /// Whether it's an enum whose variant carries fields, which is a pointer to the tag and them
+    pub fn has_fields(&self) -> bool {
+        matches!(self, Type::Enum(variants) if variants.values().any(|fields| !fields.is_empty()))
+    }
+
+    /// Type that may be null, which is itself if it already is
+    pub fn nullable(self) -> Type {
+        match self {
+            Type::Nullable(_) => self,
+            typ => Type::Nullable(Box::new(typ)),
+        }
+    }
+
+    /// Type that's known not to be null, after it's checked
+    pub fn non_null(self) -> Type {
+        match self {
+            Type::Nullable(typ) => *typ,
+            typ => typ,
+        }
+    }
+
+    /// Type with all the nullability removed, that the memory layout doesn't depend on
+    pub fn erase_null(&self) -> Type {
+        match self {
+            Type::Nullable(typ) => typ.erase_null(),
+            Type::Array(typ) => Type::Array(Box::new(typ.erase_null())),
+            Type::Dict(dict) => Type::Dict(
+                dict.iter()
+                    .map(|(key, typ)| (key.clone(), typ.erase_null()))
+                    .collect(),
+            ),
+            Type::Enum(variants) => Type::Enum(
+                variants
+                    .iter()
+                    .map(|(key, fields)| {
+                        (key.clone(), fields.iter().map(Type::erase_null).collect())
+                    })
+                    .collect(),
+            ),
+            Type::Function(args, ret) => Type::Function(
+                args.iter().map(Type::erase_null).collect(),
+                Box::new(ret.erase_null()),
+            ),
+            Type::Generic(name, args) => {
+                Type::Generic(name.clone(), args.iter().map(Type::erase_null).collect())
+            }
+            typ => typ.clone(),
         }
     }
 }
@@ -206,14 +477,22 @@ impl PartialEq for Type {
         match (self, other) {
             (Type::Integer, Type::Integer) => true,
             (Type::Number, Type::Number) => true,
+            (Type::Long, Type::Long) => true,
+            (Type::Double, Type::Double) => true,
+            (Type::Unsigned, Type::Unsigned) => true,
             (Type::Bool, Type::Bool) => true,
             (Type::String, Type::String) => true,
             (Type::Void, Type::Void) => true,
+            (Type::Nullable(a), b) | (b, Type::Nullable(a)) => **a == *b,
             (Type::Any, _) | (_, Type::Any) => true,
             (Type::Dict(a), Type::Dict(b)) => a == b,
             (Type::Enum(a), Type::Enum(b)) => a == b,
+            (Type::Function(a, r), Type::Function(b, s)) => a == b && r == s,
             (Type::Array(a), Type::Array(b)) => a == b,
             (Type::Alias(a), Type::Alias(b)) => a == b,
+            (Type::Generic(a, x), Type::Generic(b, y)) => a == b && x == y,
+            (Type::Param(a), Type::Param(b)) => a == b,
+            (Type::Var(a), Type::Var(b)) => a == b,
             _ => false,
         }
     }
@@ -1,13 +1,18 @@
-pub const BYTES: i32 = 4;
+/// Byte size of a slot of arrays and dicts, that fits the 64-bit types
+pub const BYTES: i32 = 8;
+/// Byte size of a pointer, and the 32-bit types
+pub const WORD: i32 = 4;
 pub const SPACE: [&str; 5] = [" ", "　", "\n", "\t", "\r"];
-pub const OPERATOR: [&str; 23] = [
-    "+", "-", "*", "/", "%", "==", "=", "!=", "<<", ">>", "<=", ">=", "<", ">", "&&", "||", "&",
-    "|", "^", ":", "!", "?", "~",
+pub const OPERATOR: [&str; 25] = [
+    "+", "->", "-", "*", "/", "%", "==", "=>", "=", "!=", "<<", ">>", "<=", ">=", "<", ">", "&&",
+    "||", "&", "|", "^", ":", "!", "?", "~",
 ];
-pub const RESERVED: [&str; 15] = [
+pub const RESERVED: [&str; 17] = [
     "pub", "let", "type", "if", "then", "else", "while", "loop", "break", "next", "return",
-    "import", "overload", "try", "catch",
+    "import", "overload", "try", "catch", "match", "fn",
 ];
+/// Functions whose calls are parsed into their own expressions, that can't be defined
+pub const BUILTIN: [&str; 2] = ["memcpy", "free"];
 
 #[macro_export]
 macro_rules! expand_local {
@@ -22,6 +27,7 @@ macro_rules! expand_local {
     };
 }
 
+/// Globals are exported as `global.name`, for the host to read and restore them
 #[macro_export]
 macro_rules! expand_global {
     ($ctx: expr) => {
@@ -31,8 +37,9 @@ macro_rules! expand_global {
                 .iter()
                 .map(|(name, typ)| {
                     Some(format!(
-                        "(global ${name} (mut {typ}) ({typ}.const 0))",
-                        typ = typ.compile($ctx)?
+                        "(global ${name} (export \"global.{name}\") (mut {}) {})",
+                        typ.compile($ctx)?,
+                        $ctx.initial(typ)?
                     ))
                 })
                 .collect::<Option<Vec<String>>>()?
@@ -42,12 +49,18 @@ macro_rules! expand_global {
 
 #[macro_export]
 macro_rules! is_ptr {
-    ($typ: expr, $ctx: expr) => {
-        matches!(
-            $typ.infer($ctx)?,
-            Type::String | Type::Array(_) | Type::Dict(_)
-        )
-    };
+    ($typ: expr, $ctx: expr) => {{
+        let typ = $typ.infer($ctx)?;
+        typ.has_fields()
+            || matches!(
+                typ,
+                Type::String
+                    | Type::Array(_)
+                    | Type::Dict(_)
+                    | Type::Function(..)
+                    | Type::Nullable(_)
+            )
+    }};
 }
 
 #[macro_export]
@@ -56,9 +69,14 @@ macro_rules! type_check {
         let lhs = $lhs.infer($ctx)?.infer($ctx)?;
         let rhs = $rhs.infer($ctx)?.infer($ctx)?;
         if lhs.compare(&rhs, $ctx) {
-            Some(lhs)
+            // Type variable is bound by the comparison
+            if let Type::Var(_) = lhs {
+                lhs.infer($ctx)
+            } else {
+                Some(lhs)
+            }
         } else {
-            $ctx.error = Some(format!(
+            $ctx.report(format!(
                 "type mismatch between {} and {}",
                 lhs.format(),
                 rhs.format()
@@ -80,7 +98,7 @@ macro_rules! correct {
                 $lhs.infer($ctx)?.format(),
                 $rhs.infer($ctx)?.format()
             );
-            $ctx.error = Some(msg);
+            $ctx.report(msg);
             None
         }
     }};
@@ -118,10 +136,13 @@ macro_rules! compile_args {
 macro_rules! compile_op {
     ($oper: expr, $ctx: expr, $lhs: expr, $rhs: expr) => {{
         let mut oper = $oper.to_string();
-        let ret = type_check!($lhs, $rhs, $ctx)?.compile($ctx)?;
+        let typ = type_check!($lhs, $rhs, $ctx)?;
+        let ret = typ.compile($ctx)?;
         let [lhs, rhs] = [$lhs.compile($ctx)?, $rhs.compile($ctx)?];
-        if ret == "f32" {
+        if ret.starts_with('f') {
             oper = $oper.replace("_s", "")
+        } else if let Type::Unsigned = typ {
+            oper = $oper.replace("_s", "_u")
         }
         format!("({ret}.{oper} {lhs} {rhs})",)
     }};
@@ -133,49 +154,76 @@ macro_rules! compile_op {
 
 #[macro_export]
 macro_rules! offset_calc {
-    ($dict: expr, $offset: expr, $typ: expr) => {
-        Expr::Operator(Box::new(Op::Add(
-            Expr::Operator(Box::new(Op::Transmute(*$dict.clone(), Type::Integer))),
-            Expr::Literal(Value::Integer($offset.clone())),
-        )))
-    };
+    ($dict: expr, $offset: expr) => {{
+        let span = $dict.span();
+        Expr::Operator(
+            Box::new(Op::Add(
+                Expr::Operator(Box::new(Op::Transmute(*$dict.clone(), Type::Integer)), span),
+                Expr::Literal(Value::Integer($offset.clone()), span),
+            )),
+            span,
+        )
+    }};
 }
 
 #[macro_export]
 macro_rules! address_calc {
-    ($array: expr, $index: expr, $typ: expr) => {
-        Expr::Operator(Box::new(Op::Add(
-            Expr::Operator(Box::new(Op::Add(
-                Expr::Literal(Value::Integer(BYTES)),
-                Expr::Operator(Box::new(Op::Transmute(*$array.clone(), Type::Integer))),
-            ))),
-            Expr::Operator(Box::new(Op::Mul(
-                Expr::Operator(Box::new(Op::Mod(
-                    *$index.clone(),
-                    Expr::Peek($array.clone(), Type::Integer),
-                ))),
-                Expr::Literal(Value::Integer(BYTES)),
-            ))),
-        )))
-    };
+    ($array: expr, $index: expr) => {{
+        let span = $array.span().to($index.span());
+        Expr::Operator(
+            Box::new(Op::Add(
+                Expr::Operator(
+                    Box::new(Op::Add(
+                        Expr::Literal(Value::Integer(BYTES), span),
+                        Expr::Operator(
+                            Box::new(Op::Transmute(*$array.clone(), Type::Integer)),
+                            span,
+                        ),
+                    )),
+                    span,
+                ),
+                Expr::Operator(
+                    Box::new(Op::Mul(
+                        Expr::Operator(
+                            Box::new(Op::Mod(
+                                *$index.clone(),
+                                Expr::Peek($array.clone(), Type::Integer, span),
+                            )),
+                            span,
+                        ),
+                        Expr::Literal(Value::Integer(BYTES), span),
+                    )),
+                    span,
+                ),
+            )),
+            span,
+        )
+    }};
 }
 
 #[macro_export]
 macro_rules! overload {
     ($self: expr, $ctx: expr, $method: ident) => {{
+        // Operands are checked again below, so errors of this attempt are discarded
+        let errors = $ctx.errors.len();
         let mut overload = || {
             if let Some((lhs, rhs)) = $self.binop_term() {
                 let lhs_typ = lhs.infer($ctx)?.restore_alias($ctx);
                 let rhs_typ = rhs.infer($ctx)?.restore_alias($ctx);
                 let key = ($self.get_overload_id()?, (lhs_typ, rhs_typ));
                 let func = key_from_value!(&$ctx.overload, key)?;
-                Some(Expr::Call(func.clone(), vec![lhs, rhs]).$method($ctx))
+                Some(Expr::Call(func.clone(), vec![lhs, rhs], $ctx.span).$method($ctx))
             } else if let Op::Cast(lhs, rhs) = $self.clone() {
                 let lhs_typ = lhs.infer($ctx)?.restore_alias($ctx);
                 let rhs_typ = rhs.infer($ctx)?.restore_alias($ctx);
                 let key = ($self.get_overload_id()?, (lhs_typ, rhs_typ));
                 let func = key_from_value!(&$ctx.overload, key)?;
-                Some(Expr::Call(func.clone(), vec![lhs]).$method($ctx))
+                Some(Expr::Call(func.clone(), vec![lhs], $ctx.span).$method($ctx))
+            } else if let Op::Neg(term) = $self.clone() {
+                let typ = term.infer($ctx)?.restore_alias($ctx);
+                let key = ($self.get_overload_id()?, (typ, Type::Void));
+                let func = key_from_value!(&$ctx.overload, key)?;
+                Some(Expr::Call(func.clone(), vec![term], $ctx.span).$method($ctx))
             } else {
                 None
             }
@@ -183,29 +231,31 @@ macro_rules! overload {
         if let Some(overloaded) = overload() {
             return overloaded;
         }
+        $ctx.errors.truncate(errors);
     }};
 }
 
 #[macro_export]
 macro_rules! check_args {
     ($args: expr, $ctx: expr) => {
+        let outer = $ctx.span;
         for arg in $args {
-            let Expr::Operator(oper) = arg else {
+            $ctx.span = arg.span();
+            let Expr::Operator(oper, _) = arg else {
                 let msg = "function argument definition needs type annotation";
-                $ctx.error = Some(msg.to_string());
+                $ctx.report(msg);
                 return None;
             };
-            let Op::Cast(Expr::Variable(name), typ) = *oper.clone() else {
+            let Op::Cast(Expr::Variable(name, _), typ) = *oper.clone() else {
                 let msg = "function argument name should be identifier";
-                $ctx.error = Some(msg.to_string());
+                $ctx.report(msg);
                 return None;
             };
-            if let Some(typ) = typ.infer($ctx) {
-                $ctx.argument.insert(name.to_string(), typ);
-            } else {
-                $ctx.argument.insert(name.to_string(), typ);
-            }
+            // Annotation that isn't resolved is kept as written, and reported where it's used
+            let typ = $ctx.tolerate(|ctx| typ.infer(ctx)).unwrap_or(typ);
+            $ctx.argument.insert(name.to_string(), typ);
         }
+        $ctx.span = outer;
     };
 }
 
@@ -214,7 +264,7 @@ macro_rules! arglen_check {
     ($name: expr, $args: expr, $params: expr, $typ: literal, $ctx: expr) => {
         if $args.len() != $params.len() {
             let (typ, paramlen, arglen) = ($typ, $params.len(), $args.len());
-            $ctx.error = Some(format!(
+            $ctx.report(format!(
                 "arguments of {typ} `{}` length should be {paramlen}, but passed {arglen} values",
                 $name
             ));
@@ -4,57 +4,112 @@ use crate::*;
 pub enum Value {
     Integer(i32),
     Number(f32),
+    Long(i64),
+    Double(f64),
+    Unsigned(u32),
     Bool(bool),
     Array(Vec<Expr>),
     Dict(IndexMap<String, Expr>),
-    Enum(Type, String),
+    /// Variant of the enum with the values of its fields
+    Enum(Type, String, Vec<Expr>),
     String(String),
 }
 
 impl Node for Value {
-    fn parse(source: &str) -> Option<Self> {
-        // Integer literal
-        if let Ok(n) = source.parse::<i32>() {
-            Some(Value::Integer(n))
-        // Number literal
-        } else if let Ok(n) = source.parse::<f32>() {
-            Some(Value::Number(n))
-        // Boolean literal `true | false`
-        } else if let Ok(n) = source.parse::<bool>() {
-            Some(Value::Bool(n))
-        // String literal `"..."`
-        } else if source.starts_with("\"") && source.ends_with("\"") {
-            let source = source.get(1..source.len() - 1)?;
-            Some(Value::String(source.to_string()))
-        // Array `[expr, ...]`
-        } else if source.starts_with("[") && source.ends_with("]") {
-            let source = source.get(1..source.len() - 1)?.trim();
-            let elms = tokenize(source, &[","], false, true, false)?;
-            let elms = elms.iter().map(|i| Expr::parse(&i));
-            Some(Value::Array(elms.collect::<Option<Vec<_>>>()?))
-        // Dict `@{ field: expr, ... }`
-        } else if source.starts_with("@{") && source.ends_with("}") {
-            let token = source.get(2..source.len() - 1)?.trim();
-            let mut result = IndexMap::new();
-            for line in tokenize(token, &[","], false, true, false)? {
-                let (name, value) = line.trim().split_once(":")?;
-                let mut name = name.trim().to_owned();
-                if !is_identifier(&mut name) {
-                    return None;
+    fn parse(p: &mut Parser) -> Option<Self> {
+        let start = p.index;
+        match p.peek().cloned() {
+            // Integer and number literal, that can be negative like `-1`
+            Some(
+                Token::Int(_)
+                | Token::Float(_)
+                | Token::Long(_)
+                | Token::Double(_)
+                | Token::Unsigned(_)
+                | Token::Operator("-"),
+            ) => {
+                let sign = if p.eat(Token::Operator("-")).is_some() {
+                    -1
+                } else {
+                    1
                 };
-                result.insert(name, Expr::parse(value)?);
+                match p.peek().cloned() {
+                    Some(Token::Int(n)) => {
+                        p.advance();
+                        let n = i64::try_from(n).map(|n| i32::try_from(sign * n));
+                        let Ok(Ok(n)) = n else {
+                            return p.error("integer literal is out of range", p.span_from(start));
+                        };
+                        Some(Value::Integer(n))
+                    }
+                    Some(Token::Float(n)) => {
+                        p.advance();
+                        Some(Value::Number((sign as f64 * n) as f32))
+                    }
+                    Some(Token::Long(n)) => {
+                        p.advance();
+                        let Ok(n) = i64::try_from(sign as i128 * n as i128) else {
+                            return p.error("long literal is out of range", p.span_from(start));
+                        };
+                        Some(Value::Long(n))
+                    }
+                    Some(Token::Double(n)) => {
+                        p.advance();
+                        Some(Value::Double(sign as f64 * n))
+                    }
+                    Some(Token::Unsigned(n)) => {
+                        p.advance();
+                        if sign < 0 && n != 0 {
+                            let msg = "unsigned literal can't be negative";
+                            return p.error(msg, p.span_from(start));
+                        }
+                        let Ok(n) = u32::try_from(n) else {
+                            return p.error("unsigned literal is out of range", p.span_from(start));
+                        };
+                        Some(Value::Unsigned(n))
+                    }
+                    _ => p.expected("number"),
+                }
+            }
+            // Boolean literal `true | false`
+            Some(Token::Ident(name)) if name == "true" || name == "false" => {
+                p.advance();
+                Some(Value::Bool(name == "true"))
+            }
+            // String literal `"..."`
+            Some(Token::Str(str)) => {
+                p.advance();
+                Some(Value::String(str))
             }
-            Some(Value::Dict(result))
-        } else {
-            None
+            // Array `[expr, ...]`
+            Some(Token::Punct('[')) => {
+                let open = p.expect(Token::Punct('['))?;
+                Some(Value::Array(p.list(open, ']', Expr::parse)?))
+            }
+            // Dict `@{ field: expr, ... }`
+            Some(Token::Punct('@')) => {
+                p.advance();
+                let open = p.expect(Token::Punct('{'))?;
+                let fields = p.list(open, '}', |p| {
+                    let name = p.ident()?;
+                    p.expect(Token::Operator(":"))?;
+                    Some((name, Expr::parse(p)?))
+                })?;
+                Some(Value::Dict(fields.into_iter().collect()))
+            }
+            _ => p.expected("value"),
         }
     }
 
     fn compile(&self, ctx: &mut Compiler) -> Option<String> {
-        let value = |n| Box::new(Expr::Literal(Value::Integer(n)));
+        let span = ctx.span;
+        let value = |n| Box::new(Expr::Literal(Value::Integer(n), span));
         Some(match self {
             Value::Number(n) => format!("(f32.const {n})"),
             Value::Integer(n) => format!("(i32.const {n})"),
+            Value::Long(n) => format!("(i64.const {n})"),
+            Value::Double(n) => format!("(f64.const {n})"),
+            Value::Unsigned(n) => format!("(i32.const {n})"),
             Value::Bool(n) => value(if *n { 1 } else { 0 }).compile(ctx)?,
             Value::String(str) => {
                 let result = value(ctx.allocator).compile(ctx)?;
@@ -64,86 +119,68 @@ impl Node for Value {
                 result
             }
             Value::Array(array) => {
-                let Type::Array(inner_type) = self.infer(ctx)? else {
+                let typ = self.infer(ctx)?;
+                let Type::Array(inner_type) = typ.clone() else {
                     return None;
                 };
-                let array = array.clone();
-                let mut result: Vec<_> = vec![];
-                let pointer;
-
-                if is_ptr!(inner_type, ctx) {
-                    let mut inner_codes = vec![];
-                    for elm in array.clone() {
-                        inner_codes.push(elm.compile(ctx)?)
-                    }
-                    pointer = ctx.allocator;
-                    let poke = Expr::Poke(value(ctx.allocator), value(array.len() as i32));
-                    result.push(poke.compile(ctx)?);
-                    ctx.allocator += BYTES;
-                    for code in inner_codes {
-                        result.push(format!(
-                            "({typ}.store {addr} {code})",
-                            typ = inner_type.compile(ctx)?,
-                            addr = value(ctx.allocator).compile(ctx)?,
-                        ));
-                        ctx.allocator += BYTES;
-                    }
-                } else {
-                    pointer = ctx.allocator;
-                    let poke = Expr::Poke(value(ctx.allocator), value(array.len() as i32));
-                    result.push(poke.compile(ctx)?);
+                // Elements are compiled first, as they may place their own static data
+                let mut elements = vec![];
+                for elm in array {
+                    type_check!(inner_type, elm.infer(ctx)?, ctx)?;
+                    elements.push(elm.compile(ctx)?);
+                }
+                if ctx.is_object(&typ)? {
+                    let index = ctx.layout(&typ)?;
+                    let length = array.len();
+                    return Some(format!(
+                        "(array.new_fixed $type.{index} {length} {})",
+                        join!(elements)
+                    ));
+                }
+                let pointer = ctx.allocator;
+                let poke = Expr::Poke(value(ctx.allocator), value(array.len() as i32), span);
+                let mut result = vec![poke.compile(ctx)?];
+                ctx.allocator += BYTES;
+                // Pointer elements are stored through the collector, that counts the references
+                for code in elements {
+                    let addr = value(ctx.allocator).compile(ctx)?;
+                    result.push(ctx.store(&inner_type, addr, code)?);
                     ctx.allocator += BYTES;
-                    for elm in array {
-                        type_check!(inner_type, elm.infer(ctx)?, ctx)?;
-                        let poke = Expr::Poke(value(ctx.allocator), Box::new(elm));
-                        result.push(poke.compile(ctx)?);
-                        ctx.allocator += BYTES
-                    }
                 }
                 join!([value(pointer).compile(ctx)?, join!(result)])
             }
             Value::Dict(dict) => {
                 let mut result: Vec<_> = vec![];
-                let Type::Dict(_) = self.infer(ctx)? else {
+                let typ = self.infer(ctx)?;
+                let Type::Dict(_) = typ else {
                     return None;
                 };
+                if ctx.is_object(&typ)? {
+                    let mut fields = vec![];
+                    for elm in dict.values() {
+                        fields.push(elm.compile(ctx)?);
+                    }
+                    let index = ctx.layout(&typ)?;
+                    return Some(format!("(struct.new $type.{index} {})", join!(fields)));
+                }
 
+                // Fields are compiled first, as they may place their own static data
                 let mut prestore = IndexMap::new();
                 for (name, elm) in dict {
-                    let typ = elm.infer(ctx)?;
-                    if is_ptr!(typ, ctx) {
-                        prestore.insert(name, elm.compile(ctx)?);
-                    }
+                    prestore.insert(name, elm.compile(ctx)?);
                 }
 
                 let pointer = ctx.allocator;
                 for (name, elm) in dict {
                     let typ = elm.infer(ctx)?;
-                    result.push(format!(
-                        "({typ}.store {addr} {value})",
-                        typ = typ.clone().compile(ctx)?,
-                        addr = value(ctx.allocator).compile(ctx)?,
-                        value = prestore.get(name).cloned().or_else(|| elm.compile(ctx))?
-                    ));
+                    let addr = value(ctx.allocator).compile(ctx)?;
+                    result.push(ctx.store(&typ, addr, prestore[name].clone())?);
                     ctx.allocator += BYTES;
                 }
 
                 join!([value(pointer).compile(ctx)?, join!(result)])
             }
-            Value::Enum(typ, key) => {
-                let typ = typ.infer(ctx)?;
-                let Type::Enum(enum_type) = typ.clone() else {
-                    let error_message = format!("can't access enumerator to {}", typ.format());
-                    ctx.error = Some(error_message);
-                    return None;
-                };
-                let Some(variant) = enum_type.iter().position(|item| item == key) else {
-                    let error_message = format!("`{key}` is invalid variant of {}", typ.format());
-                    ctx.error = Some(error_message);
-                    return None;
-                };
-                value(variant as i32).compile(ctx)?
-            }
+            Value::Enum(typ, key, args) => ctx.variant(typ, key, args)?,
         })
     }
 
@@ -151,6 +188,9 @@ impl Node for Value {
         Some(match self {
             Value::Number(_) => Type::Number,
             Value::Integer(_) => Type::Integer,
+            Value::Long(_) => Type::Long,
+            Value::Double(_) => Type::Double,
+            Value::Unsigned(_) => Type::Unsigned,
             Value::Bool(_) => Type::Bool,
             Value::String(_) => Type::String,
             Value::Array(e) => {
@@ -158,8 +198,8 @@ impl Node for Value {
                 for e in e.iter().skip(1) {
                     let typ = e.infer(ctx)?;
                     if typ != origin {
-                        let errmsg = "array elements must be of the same type";
-                        ctx.error = Some(errmsg.to_owned());
+                        ctx.span = e.span();
+                        ctx.report("array elements must be of the same type");
                         return None;
                     }
                 }
@@ -173,7 +213,26 @@ impl Node for Value {
                 }
                 Type::Dict(result)
             }
-            Value::Enum(typ, _) => typ.infer(ctx)?,
+            Value::Enum(typ, key, args) => {
+                let (typ, _, fields) = ctx.fields_of(typ, key)?;
+                ctx.arity(key, fields.len(), args.len())?;
+                for (arg, field) in args.iter().zip(&fields) {
+                    ctx.span = arg.span();
+                    ctx.expect_value(field, arg)?;
+                }
+                typ
+            }
         })
     }
 }
+
+impl Value {
+    /// Integer literal of the type, whose bits are the same as `n` in `int`
+    pub fn integer(n: i32, typ: &Type) -> Value {
+        match typ {
+            Type::Long => Value::Long(n as i64),
+            Type::Unsigned => Value::Unsigned(n as u32),
+            _ => Value::Integer(n),
+        }
+    }
+}
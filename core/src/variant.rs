@@ -0,0 +1,128 @@
+use crate::*;
+
+impl Compiler {
+    /// Enum type, the tag and the field types of the variant `key`
+    pub(crate) fn fields_of(&mut self, typ: &Type, key: &str) -> Option<(Type, usize, Vec<Type>)> {
+        let typ = typ.infer(self)?;
+        let name = typ.restore_alias(self).format();
+        let Type::Enum(variants) = &typ else {
+            self.report(format!("can't access enumerator to {name}"));
+            return None;
+        };
+        let Some((tag, _, fields)) = variants.get_full(key) else {
+            self.report(format!("`{key}` is invalid variant of {name}"));
+            return None;
+        };
+        let fields = fields.clone();
+        Some((typ, tag, fields))
+    }
+
+    /// Variant has to be given as many values or bindings as its fields
+    pub(crate) fn arity(&mut self, key: &str, fields: usize, given: usize) -> Option<()> {
+        if fields != given {
+            self.report(format!(
+                "variant `{key}` has {fields} fields, but {given} are given"
+            ));
+            return None;
+        }
+        Some(())
+    }
+
+    /// Value of the variant, that's its tag, or a new object of the tag and the fields
+    /// if the enum has any
+    pub(crate) fn variant(&mut self, typ: &Type, key: &str, args: &[Expr]) -> Option<String> {
+        let (typ, tag, fields) = self.fields_of(typ, key)?;
+        if !typ.has_fields() {
+            return Some(format!("(i32.const {tag})"));
+        }
+        let mut values = vec![];
+        for (arg, field) in args.iter().zip(&fields) {
+            if self.is_object(field)? {
+                let field = field.restore_alias(self).format();
+                self.report(format!(
+                    "can't have {field} in the enum field with the Wasm GC, as enums are in the linear memory"
+                ));
+                return None;
+            }
+            values.push(arg.compile(self)?);
+        }
+        let index = self.layout(&typ)?;
+        let function = format!("variant.{index}.{tag}");
+        if !self.declare.contains_key(&function) {
+            let Type::Enum(variants) = &typ else {
+                return None;
+            };
+            let mut params = vec![];
+            let mut stores = vec![];
+            for (n, field) in fields.iter().enumerate() {
+                let field = field.compile(self)?;
+                params.push(format!("(param $field.{n} {field})"));
+                stores.push(format!(
+                    "({field}.store offset={} (local.get $ptr) (local.get $field.{n}))",
+                    (n as i32 + 1) * BYTES
+                ));
+            }
+            let code = format!(
+                concat!(
+                    "(func ${function} {params} (result i32) (local $ptr i32) ",
+                    "(local.set $ptr (call $malloc (i32.const {size}))) ",
+                    "(i32.store (local.get $ptr) (i32.const {tag})) {stores} (local.get $ptr))"
+                ),
+                function = function,
+                params = join!(params),
+                size = enum_size(variants),
+                tag = tag,
+                stores = join!(stores),
+            );
+            self.declare.insert(function.clone(), code);
+        }
+        self.adopt(&typ, format!("(call ${function} {})", join!(values)))
+    }
+
+    /// Variables that the pattern like `E#a(x, _)` binds, with their types and the offsets
+    /// of the fields
+    pub(crate) fn bindings(&mut self, pattern: &Expr) -> Option<Vec<(String, Type, i32)>> {
+        let Expr::Literal(Value::Enum(typ, key, args), _) = pattern else {
+            return Some(vec![]);
+        };
+        let (_, _, fields) = self.fields_of(typ, key)?;
+        let mut result = vec![];
+        for (n, (arg, field)) in args.iter().zip(fields).enumerate() {
+            if let Expr::Variable(name, _) = arg
+                && name != "_"
+            {
+                result.push((name.clone(), field, (n as i32 + 1) * BYTES));
+            }
+        }
+        Some(result)
+    }
+
+    /// Declare the variables that the pattern binds, for the body of the arm
+    pub(crate) fn bind(&mut self, pattern: &Expr) -> Option<()> {
+        for (name, typ, _) in self.bindings(pattern)? {
+            if self.argument.contains_key(&name) {
+                self.report("can't reassign value to argument");
+                return None;
+            }
+            if self.global.contains_key(&name) {
+                self.report(format!(
+                    "can't bind global variable `{name}` in the pattern"
+                ));
+                return None;
+            }
+            if let Some(exist) = self.variable.get(&name).cloned() {
+                type_check!(exist, typ, self)?;
+            } else {
+                self.variable.insert(name, typ);
+            }
+        }
+        Some(())
+    }
+}
+
+/// Byte size of the enum object, which is the same for all the variants
+/// so that it can be copied without knowing the tag
+pub(crate) fn enum_size(variants: &Enum) -> i32 {
+    let fields = variants.values().map(Vec::len).max().unwrap_or_default();
+    (fields as i32 + 1) * BYTES
+}
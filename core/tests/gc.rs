@@ -0,0 +1,92 @@
+use lumo_core::*;
+use wasmi::{Config, Engine, Instance, Linker, Module, Store, Val};
+
+const NODE: &str = "type Node = @{ v: int, nx: Node? }";
+
+/// Instance of the program built with the collector in the small memory
+fn instantiate(source: &str, gc: Gc) -> Result<(Store<()>, Instance), String> {
+    let mut compiler = Compiler::new();
+    compiler.gc = gc;
+    compiler.initial_pages = 1;
+    compiler.max_pages = Some(16);
+    let wat = compiler.build(source).ok_or("failed to compile")?;
+    let binary = wat::parse_str(wat).map_err(|err| err.to_string())?;
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+    let module = Module::new(&engine, &binary[..]).map_err(|err| err.to_string())?;
+    let mut store = Store::new(&engine, ());
+    store.set_fuel(100_000_000).map_err(|err| err.to_string())?;
+    let instance = Linker::<()>::new(&engine)
+        .instantiate(&mut store, &module)
+        .and_then(|pre| pre.start(&mut store))
+        .map_err(|err| err.to_string())?;
+    Ok((store, instance))
+}
+
+/// Result of calling the exported function, or the error when it traps or runs out of the fuel
+fn call(source: &str, gc: Gc, name: &str, args: &[Val]) -> Result<i32, String> {
+    let (mut store, instance) = instantiate(source, gc)?;
+    let func = instance.get_func(&store, name).ok_or("missing function")?;
+    let mut result = [Val::I32(0)];
+    func.call(&mut store, args, &mut result)
+        .map_err(|err| err.to_string())?;
+    Ok(result[0].i32().unwrap_or_default())
+}
+
+/// Result of `_start` with the end of the heap after it, that's where the allocator has reached
+fn run(source: &str, gc: Gc) -> Result<(i32, i32), String> {
+    let (mut store, instance) = instantiate(source, gc)?;
+    let start = instance
+        .get_func(&store, "_start")
+        .ok_or("missing `_start`")?;
+    let mut result = [Val::I32(0)];
+    start
+        .call(&mut store, &[], &mut result)
+        .map_err(|err| err.to_string())?;
+    let allocator = instance
+        .get_global(&store, "allocator")
+        .ok_or("missing allocator")?;
+    let end = allocator.get(&store).i32().unwrap_or_default();
+    Ok((result[0].i32().unwrap_or_default(), end))
+}
+
+#[test]
+fn heap_cycle() {
+    let source = format!(
+        "{NODE}; let i = 0;
+        while i < 100000 loop {{
+            let a = memcpy(@{{ v: i, nx: Node! }});
+            let a.nx = memcpy(@{{ v: i, nx: a }});
+            let i + 1
+        }};
+        i"
+    );
+    // Objects referring to each other keep their counts, so they're never freed
+    assert!(run(&source, Gc::Rc).is_err());
+    let (result, end) = run(&source, Gc::MarkSweep).expect("cycles should be collected");
+    assert_eq!(result, 100000);
+    let (_, base) = run("0", Gc::MarkSweep).expect("empty program");
+    assert!(end - base < 65536, "heap grew by {} bytes", end - base);
+}
+
+#[test]
+fn static_cycle() {
+    let source = format!(
+        "{NODE}; let a = @{{ v: 1, nx: Node! }}; let a.nx = a: Node?; let i = 0;
+        while i < 100000 loop {{ let b = memcpy(@{{ v: i, nx: Node! }}); let a.v = b.v; let i + 1 }};
+        a.v"
+    );
+    for gc in [Gc::Rc, Gc::MarkSweep] {
+        assert_eq!(run(&source, gc).map(|(result, _)| result), Ok(99999));
+    }
+}
+
+#[test]
+fn huge_allocation() {
+    for gc in [Gc::None, Gc::MarkSweep] {
+        assert!(call("0", gc, "malloc", &[Val::I32(i32::MAX)]).is_err());
+        assert!(call("0", gc, "malloc", &[Val::I32(-4)]).is_err());
+        assert!(call("0", gc, "malloc", &[Val::I32(12)]).is_ok());
+    }
+}
@@ -0,0 +1,67 @@
+use lumo_core::*;
+
+/// Type of the program's result, that's compiled for the instances to be generated
+fn result(source: &str) -> String {
+    let mut compiler = Compiler::new();
+    if compiler.build(source).is_none() {
+        panic!("failed to compile: {:?}", compiler.errors);
+    }
+    compiler.summary().result
+}
+
+#[test]
+fn alias_access() {
+    let pair = "type Pair<A, B> = @{ fst: A, snd: B }; \
+        let mk<A, B>(a: A, b: B): Pair<A, B> = @{ fst: a, snd: b }";
+    assert_eq!(result(&format!("{pair}; mk(1, 2.5).snd")), "num");
+    assert_eq!(result(&format!("{pair}; mk(1, 2.5).fst")), "int");
+    let list = "type L<T> = @{ v: T, n: L<T>? }; \
+        let one<T>(v: T): L<T> = memcpy(@{ v: v, n: L<T>! })";
+    assert_eq!(result(&format!("{list}; one(5).v")), "int");
+    let array = "type Arr<T> = [T]; let w<T>(x: T): Arr<T> = [x, x]";
+    assert_eq!(result(&format!("{array}; w(true)[1]")), "bool");
+}
+
+#[test]
+fn function_argument() {
+    let map = |signature: &str| {
+        format!(
+            "let map<T, U>(xs: [T], f: {signature}): [U] = {{ \
+                let ys = [f(xs[0]), f(xs[1])]; ys \
+            }}; map([1, 2], fn(x: int) => x > 1)"
+        )
+    };
+    assert_eq!(result(&map("(T) -> U")), "[bool]");
+    assert_eq!(result(&map("fn(T) -> U")), "[bool]");
+}
+
+#[test]
+fn any_at_each_call() {
+    let arrlen = "import arrlen([any]): int";
+    assert_eq!(
+        result(&format!("{arrlen}; arrlen([1, 2, 3]) + arrlen([\"a\"])")),
+        "int"
+    );
+    assert_eq!(
+        result(&format!(
+            "{arrlen}; let len<T>(xs: [T]) = arrlen(xs); len([1, 2, 3])"
+        )),
+        "int"
+    );
+    let append = "import append([any], [any]): [any]";
+    assert_eq!(result(&format!("{append}; append([1], [2])")), "[int]");
+    assert_eq!(
+        result(&format!(
+            "{append}; append([1], [2]); append([\"a\"], [\"b\"])"
+        )),
+        "[str]"
+    );
+    let mut compiler = Compiler::new();
+    compiler.build(&format!("{append}; append([1], [\"b\"])"));
+    let errors: Vec<_> = compiler
+        .errors
+        .iter()
+        .map(|err| err.message.clone())
+        .collect();
+    assert_eq!(errors, ["type mismatch between str and int"]);
+}
@@ -0,0 +1,108 @@
+use lumo_core::*;
+
+/// Summary of the program, that's compiled for the environments to be filled
+fn summary(source: &str) -> Summary {
+    let mut compiler = Compiler::new();
+    if compiler.build(source).is_none() {
+        panic!("failed to compile: {:?}", compiler.errors);
+    }
+    compiler.summary()
+}
+
+/// Messages of the errors that the program is rejected with
+fn errors(source: &str) -> Vec<String> {
+    let mut compiler = Compiler::new();
+    assert!(compiler.build(source).is_none(), "expected errors");
+    compiler
+        .errors
+        .iter()
+        .map(|err| err.message.clone())
+        .collect()
+}
+
+fn signature(func: &FunctionSummary) -> String {
+    let args: Vec<_> = func
+        .arguments
+        .iter()
+        .map(|(name, typ)| format!("{name}: {typ}"))
+        .collect();
+    format!("({}): {}", args.join(", "), func.returns)
+}
+
+#[test]
+fn body_inferred() {
+    let summary =
+        summary("let inc(x) = x + 1; let half(x) = x / 2.0; @{ i: inc(2), h: half(3.0) }");
+    assert_eq!(signature(&summary.functions["inc"]), "(x: int): int");
+    assert_eq!(signature(&summary.functions["half"]), "(x: num): num");
+    assert_eq!(summary.result, "@{ i: int, h: num }");
+    assert!(summary.instances.is_empty());
+}
+
+#[test]
+fn generalized() {
+    let summary = summary(
+        "let add(a, b) = a + b; let id(x) = x; @{ i: add(1, 2), n: add(1.5, 2.0), x: id(3) }",
+    );
+    assert_eq!(signature(&summary.functions["add"]), "(a: 'a, b: 'a): _");
+    assert_eq!(signature(&summary.functions["id"]), "(x: 'a): 'a");
+    assert_eq!(
+        signature(&summary.instances["add<int>"]),
+        "(a: int, b: int): int"
+    );
+    assert_eq!(
+        signature(&summary.instances["add<num>"]),
+        "(a: num, b: num): num"
+    );
+    assert_eq!(signature(&summary.instances["id<int>"]), "(x: int): int");
+    assert_eq!(summary.functions.len(), 2);
+    assert_eq!(summary.result, "@{ i: int, n: num, x: int }");
+}
+
+#[test]
+fn inferred_calls() {
+    assert_eq!(
+        summary("let sq(x) = x * x; let k(y) = sq(y); k(3)").result,
+        "int"
+    );
+    let summary =
+        summary("let sq(x) = x * x; let k(y) = sq(y); let m(a, b) = k(a) + sq(b); m(1.5, 2.0)");
+    assert_eq!(summary.result, "num");
+    assert_eq!(summary.instances.len(), 3);
+}
+
+#[test]
+fn exported() {
+    assert_eq!(
+        errors("pub let add(a, b) = a + b"),
+        ["function argument `b` needs type annotation, as its type isn't inferred from the body"]
+    );
+    assert_eq!(
+        errors("pub let id(x) = x"),
+        ["can't export function `id`, as any type can be given to argument `x`"]
+    );
+    assert_eq!(
+        signature(&summary("pub let inc(x) = x + 1").exports["inc"]),
+        "(x: int): int"
+    );
+}
+
+#[test]
+fn builtin_names() {
+    let source = "let free = 3; let a = memcpy(@{ v: free }); let v = a.v + free; a.free(); v";
+    assert_eq!(summary(source).result, "int");
+    assert_eq!(
+        errors("let free(x: int) = x; 0"),
+        ["can't define function `free`, as it's built-in"]
+    );
+    assert_eq!(
+        errors("import memcpy(int): int; 0"),
+        ["can't import function `memcpy`, as it's built-in"]
+    );
+}
+
+#[test]
+fn unterminated_comment() {
+    assert_eq!(errors("let a = 1; ~~ note\na"), ["unterminated comment"]);
+    assert_eq!(summary("let a = 1; ~~ note ~~ a").result, "int");
+}
@@ -0,0 +1,107 @@
+use lumo_core::*;
+
+const LIST: &str = "type L = @{ v: int, n: L? }; let a = memcpy(@{ v: 1, n: L! })";
+
+/// Messages of the errors in the program, that's empty if it's compiled
+fn errors(source: &str) -> Vec<String> {
+    let mut compiler = Compiler::new();
+    compiler.build(source);
+    compiler
+        .errors
+        .iter()
+        .map(|err| err.message.clone())
+        .collect()
+}
+
+#[test]
+fn nested() {
+    let strict = "type L = @{ v: int, n: L }; let f(x: L) = x.n.v; f(memcpy(@{ v: 1, n: L! }))";
+    assert_eq!(
+        errors(strict),
+        ["possibly null L? is given where L is expected"]
+    );
+    // New object can be given where its fields allow null, but a variable can't
+    let f = "let f(x: L) = x.v";
+    assert!(errors(&format!("{LIST}; {f}; f(memcpy(@{{ v: 2, n: a }}))")).is_empty());
+    assert!(errors(&format!("{LIST}; let a.n = @{{ v: 2, n: a }}; 0")).is_empty());
+    assert!(errors(&format!("{LIST}; let f(xs: [L?]) = 0; f([a, a])")).is_empty());
+    assert_eq!(
+        errors(&format!(
+            "{LIST}; {f}; let b = memcpy(@{{ v: 2, n: a }}); f(b)"
+        )),
+        ["type mismatch between @{ v: int, n: L } and @{ v: int, n: L? }"]
+    );
+    assert!(errors(&format!("{LIST}; {f}; f(memcpy(@{{ v: 2, n: a: L? }}))")).is_empty());
+}
+
+#[test]
+fn field_narrowing() {
+    assert!(errors(&format!("{LIST}; {{ if a.n? then a.n.v else 0 }}")).is_empty());
+    assert!(
+        errors(&format!(
+            "{LIST}; {{ if a.n? then {{ if a.n.n? then a.n.n.v else 1 }} else 0 }}"
+        ))
+        .is_empty()
+    );
+    let reassigned = [
+        "let a = memcpy(a); a.n.v",
+        "let a.n = L!; a.n.v",
+        "let b = a; let b.n = L!; a.n.v",
+    ];
+    for body in reassigned {
+        assert_eq!(
+            errors(&format!("{LIST}; {{ if a.n? then {{ {body} }} else 0 }}")),
+            ["can't field access to possibly null L?"],
+            "{body}"
+        );
+    }
+    let inner = "let f(a: L) = a.n.v; f(a)";
+    assert_eq!(
+        errors(&format!("{LIST}; {{ if a.n? then {{ {inner} }} else 0 }}")),
+        ["can't field access to possibly null L?"]
+    );
+}
+
+#[test]
+fn check_and_build() {
+    let checked = |source: &str| {
+        let mut compiler = Compiler::new();
+        let ast = compiler.parse(source).expect("failed to parse");
+        compiler.check(&ast);
+        let errors: Vec<_> = compiler
+            .errors
+            .iter()
+            .map(|err| err.message.clone())
+            .collect();
+        errors
+    };
+    let clone = "let clone(self: L) = { let object = self.memcpy(); if object.n? then { let object.n = clone(self.n) }; object }";
+    let programs = [
+        format!("{LIST}; let c = a; {{ if c? then {{ let c = c.n }} }}; 0"),
+        format!("{LIST}; let c = a; let s = 0; while c? loop {{ let s + c.v; let c = c.n }}; s"),
+        format!("{LIST}; {clone}; clone(a).v"),
+        format!(
+            "{LIST}; {}; clone(a).v",
+            clone.replace("object.n?", "self.n?")
+        ),
+    ];
+    for source in programs {
+        assert_eq!(checked(&source), errors(&source), "{source}");
+    }
+    assert_eq!(
+        errors(&format!("{LIST}; {clone}; clone(a).v")),
+        ["possibly null L? is given where L is expected"]
+    );
+}
+
+#[test]
+fn nullable_cast() {
+    let mk = "let mk(v: int, n: L?) = memcpy(@{ v: v, n: n })";
+    assert!(errors(&format!("{LIST}; {mk}; let b = mk(1, a): L?; 0")).is_empty());
+    assert!(
+        errors(&format!(
+            "{LIST}; {mk}; let b = @{{ v: 2, n: mk(1, a): L? }}; 0"
+        ))
+        .is_empty()
+    );
+}
@@ -0,0 +1,111 @@
+use lumo_core::*;
+
+/// Source code of the expression with every operation parenthesized
+fn group(expr: &Expr) -> String {
+    let Expr::Operator(oper, _) = expr else {
+        return match expr {
+            Expr::Literal(Value::Integer(n), _) => n.to_string(),
+            Expr::Variable(name, _) => name.clone(),
+            Expr::Field(obj, name, _) => format!("{}.{name}", group(obj)),
+            Expr::Call(name, args, _) => {
+                let args: Vec<_> = args.iter().map(group).collect();
+                format!("{name}({})", args.join(", "))
+            }
+            _ => panic!("unexpected term {expr:?}"),
+        };
+    };
+    let binary = |lhs: &Expr, op: &str, rhs: &Expr| format!("({} {op} {})", group(lhs), group(rhs));
+    match oper.as_ref() {
+        Op::Add(lhs, rhs) => binary(lhs, "+", rhs),
+        Op::Sub(lhs, rhs) => binary(lhs, "-", rhs),
+        Op::Mul(lhs, rhs) => binary(lhs, "*", rhs),
+        Op::Div(lhs, rhs) => binary(lhs, "/", rhs),
+        Op::Mod(lhs, rhs) => binary(lhs, "%", rhs),
+        Op::Shr(lhs, rhs) => binary(lhs, ">>", rhs),
+        Op::Shl(lhs, rhs) => binary(lhs, "<<", rhs),
+        Op::Eql(lhs, rhs) => binary(lhs, "==", rhs),
+        Op::Neq(lhs, rhs) => binary(lhs, "!=", rhs),
+        Op::Lt(lhs, rhs) => binary(lhs, "<", rhs),
+        Op::Gt(lhs, rhs) => binary(lhs, ">", rhs),
+        Op::LtEq(lhs, rhs) => binary(lhs, "<=", rhs),
+        Op::GtEq(lhs, rhs) => binary(lhs, ">=", rhs),
+        Op::BAnd(lhs, rhs) => binary(lhs, "&", rhs),
+        Op::BOr(lhs, rhs) => binary(lhs, "|", rhs),
+        Op::XOr(lhs, rhs) => binary(lhs, "^", rhs),
+        Op::LAnd(lhs, rhs) => binary(lhs, "&&", rhs),
+        Op::LOr(lhs, rhs) => binary(lhs, "||", rhs),
+        Op::Neg(term) => format!("(-{})", group(term)),
+        Op::BNot(term) => format!("(~{})", group(term)),
+        Op::LNot(term) => format!("(!{})", group(term)),
+        Op::Cast(term, typ) => format!("({}: {})", group(term), typ.format()),
+        Op::NullCheck(term) => format!("({}?)", group(term)),
+        Op::Nullable(typ) => format!("({}!)", typ.format()),
+        Op::Transmute(..) => panic!("unexpected operation {oper:?}"),
+    }
+}
+
+fn parse(source: &str) -> String {
+    group(&Parser::parse::<Expr>(source, 0).expect("failed to parse"))
+}
+
+#[test]
+fn arithmetic() {
+    assert_eq!(parse("1 + 2 * 3"), "(1 + (2 * 3))");
+    assert_eq!(parse("1 * 2 + 3"), "((1 * 2) + 3)");
+    assert_eq!(parse("a - b - c"), "((a - b) - c)");
+    assert_eq!(parse("a / b % c * d"), "(((a / b) % c) * d)");
+    assert_eq!(parse("(1 + 2) * 3"), "((1 + 2) * 3)");
+}
+
+#[test]
+fn bitwise() {
+    assert_eq!(parse("a << 1 + b"), "(a << (1 + b))");
+    assert_eq!(parse("a & b << 2"), "(a & (b << 2))");
+    assert_eq!(parse("a | b ^ c & d"), "(a | (b ^ (c & d)))");
+    assert_eq!(parse("a >> b >> c"), "((a >> b) >> c)");
+}
+
+#[test]
+fn comparison() {
+    assert_eq!(parse("a + 1 == b * 2"), "((a + 1) == (b * 2))");
+    assert_eq!(parse("a | b < c"), "((a | b) < c)");
+    assert_eq!(parse("a <= b"), "(a <= b)");
+    assert!(Parser::parse::<Expr>("a < b < c", 0).is_err());
+    assert!(Parser::parse::<Expr>("a == b != c", 0).is_err());
+}
+
+#[test]
+fn logical() {
+    assert_eq!(parse("a == b && c"), "((a == b) && c)");
+    assert_eq!(parse("a || b && c"), "(a || (b && c))");
+    assert_eq!(parse("a && b || c && d"), "((a && b) || (c && d))");
+    assert_eq!(parse("a < b && c >= d"), "((a < b) && (c >= d))");
+}
+
+#[test]
+fn cast() {
+    assert_eq!(parse("a + b: str"), "(a + (b: str))");
+    assert_eq!(parse("a: num * 2"), "((a: num) * 2)");
+    assert_eq!(parse("a: int: str"), "((a: int): str)");
+    assert_eq!(parse("f(x): num + 1"), "((f(x): num) + 1)");
+}
+
+#[test]
+fn prefix() {
+    assert_eq!(parse("!a && b"), "((!a) && b)");
+    assert_eq!(parse("~a & b"), "((~a) & b)");
+    assert_eq!(parse("!!a"), "(!(!a))");
+    assert_eq!(parse("~a: num"), "((~a): num)");
+    assert_eq!(parse("-a * b"), "((-a) * b)");
+    assert_eq!(parse("-a.b - -c"), "((-a.b) - (-c))");
+    assert_eq!(parse("2 - -2147483648"), "(2 - -2147483648)");
+    assert_eq!(parse("-f(x)?"), "(-(f(x)?))");
+}
+
+#[test]
+fn suffix() {
+    assert_eq!(parse("a.b? && c"), "((a.b?) && c)");
+    assert_eq!(parse("!a.b?"), "(!(a.b?))");
+    assert_eq!(parse("a.b?: int"), "((a.b?): int)");
+    assert_eq!(parse("T! == x"), "((T!) == x)");
+}
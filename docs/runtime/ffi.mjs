@@ -1,11 +1,25 @@
-const BYTES = 4;
+const BYTES = 8;
+const GETTERS = {
+    num: "getFloat32",
+    long: "getBigInt64",
+    double: "getFloat64",
+    uint: "getUint32",
+};
+const SETTERS = { num: "setFloat32", long: "setBigInt64", double: "setFloat64" };
 
-export function read(instance, type, value) {
+export function read(instance, type, value, aliases = {}) {
     const memoryView = new Uint8Array(instance.exports.mem.buffer);
-    if (type == "int") {
+    if (type.type == "alias") {
+        const definition = aliases[type.name];
+        return definition ? read(instance, definition, value, aliases) : null;
+    } else if (type == "int") {
         return value;
     } else if (type == "num") {
         return Math.round(value * 1e6) / 1e6;
+    } else if (type == "long" || type == "double") {
+        return value;
+    } else if (type == "uint") {
+        return value >>> 0;
     } else if (type == "bool") {
         return value != 0;
     } else if (type == "str") {
@@ -21,12 +35,12 @@ export function read(instance, type, value) {
         const innerType = type.element;
         let [result, addr] = [[], value + BYTES];
 
-        const length = concatBytes(memoryView.slice(value, addr), false);
+        const length = concatBytes(memoryView.slice(value, addr));
         for (let index = 0; index < length; index++) {
             const sliced = memoryView.slice(addr, addr + BYTES);
-            const elem = concatBytes(sliced, innerType == "num");
+            const elem = concatBytes(sliced, innerType);
 
-            result.push(read(instance, innerType, elem));
+            result.push(read(instance, innerType, elem, aliases));
             addr += BYTES;
         }
         return result;
@@ -39,68 +53,121 @@ export function read(instance, type, value) {
             const addr = pointer + offset;
             const sliced = memoryView.slice(addr, addr + BYTES);
 
-            const value = concatBytes(sliced, field == "num");
-            const fieldType = field.type == "alias" ? type : field;
-
-            result[name] = read(instance, fieldType, value);
+            const value = concatBytes(sliced, field);
+            result[name] = read(instance, field, value, aliases);
             offset += BYTES;
         }
         return result;
     } else if (type.type == "enum") {
-        return type.enum[value];
-    } else if (type.type == "alias") {
-        return null;
+        if (!hasFields(type)) return type.enum[value];
+        if (value == -1) return null;
+        // Object has the tag, followed by the fields of the variant
+        const tag = type.enum[concatBytes(memoryView.slice(value, value + BYTES))];
+        const fields = type.fields[tag].map((field, index) => {
+            const addr = value + BYTES * (index + 1);
+            const sliced = memoryView.slice(addr, addr + BYTES);
+            return read(instance, field, concatBytes(sliced, field), aliases);
+        });
+        return { tag, fields };
+    } else if (type.type == "function") {
+        if (value == -1) return null;
+        // Closure is passed as the environment to the function at the table index in it
+        const view = new DataView(instance.exports.mem.buffer, value, BYTES);
+        const func = instance.exports.closures.get(view.getInt32(0, true));
+        const closure = (...args) => {
+            const values = type.arguments.map((arg, index) =>
+                write(instance, arg, args[index], aliases),
+            );
+            return read(instance, type.returns, func(value, ...values), aliases);
+        };
+        closure.pointer = value;
+        return closure;
     } else {
         return type;
     }
 }
 
-export function write(instance, type, value) {
-    const reader = (type) => (type == "num" ? "setFloat32" : "setInt32");
-    const buffer = instance.exports.mem.buffer;
+export function write(instance, type, value, aliases = {}) {
+    const setter = (type) => SETTERS[type] ?? "setInt32";
     if (type == null) return null;
-    else if (type == "int") return value;
-    else if (type == "num") return value;
+    else if (type.type == "alias") {
+        return write(instance, aliases[type.name], value, aliases);
+    } else if (["int", "num", "double", "uint"].includes(type)) return value;
+    else if (type == "long") return BigInt(value);
+    else if (type == "bool") return value ? 1 : 0;
+    else if (value == null) return -1;
     else if (type == "str") {
         const utf8 = new TextEncoder().encode(value + "\0");
         const ptr = instance.exports.malloc(utf8.length);
-        new Uint8Array(buffer, ptr, utf8.length).set(utf8);
+        new Uint8Array(instance.exports.mem.buffer, ptr, utf8.length).set(utf8);
         return ptr;
     } else if (type.type == "array") {
         let array = [];
-        for (let elm of value) array.push(write(instance, type.element, elm));
+        for (let elm of value)
+            array.push(write(instance, type.element, elm, aliases));
 
         const size = BYTES * value.length + BYTES;
         const ptr = instance.exports.malloc(size);
-        const view = new DataView(buffer, ptr, size);
+        const view = new DataView(instance.exports.mem.buffer, ptr, size);
         let addr = 0;
 
         view.setInt32(addr, value.length, true);
         addr += BYTES;
 
         for (let elm of array) {
-            view[reader(type.element)](addr, elm, true);
+            view[setter(type.element)](addr, elm, true);
             addr += BYTES;
         }
         return ptr;
     } else if (type.type == "dict") {
-        for (let [name, field] of Object.entries(type.fields))
-            type.fields[name] = write(instance, field.type, value[name]);
+        const fields = Object.entries(type.fields).map(([name, field]) => [
+            field,
+            write(instance, field, value[name], aliases),
+        ]);
 
-        const size = field.length * BYTES;
+        const size = fields.length * BYTES;
         const ptr = instance.exports.malloc(size);
-        const view = new DataView(buffer, ptr, size);
+        const view = new DataView(instance.exports.mem.buffer, ptr, size);
 
         let addr = 0;
-        for (let [_name, field] of Object.entries(type.fields)) {
-            view[reader(type.element)](addr, field, true);
+        for (let [field, elm] of fields) {
+            view[setter(field)](addr, elm, true);
+            addr += BYTES;
+        }
+        return ptr;
+    } else if (type.type == "function") {
+        // Only closures that came from the module can be passed back
+        return value.pointer ?? -1;
+    } else if (type.type == "enum") {
+        if (!hasFields(type)) return type.enum.indexOf(value);
+        const fields = type.fields[value.tag].map((field, index) => [
+            field,
+            write(instance, field, value.fields[index], aliases),
+        ]);
+
+        // Every variant has the same size, which is for the one with the most fields
+        const counts = Object.values(type.fields).map((fields) => fields.length);
+        const size = BYTES * (Math.max(...counts) + 1);
+        const ptr = instance.exports.malloc(size);
+        const view = new DataView(instance.exports.mem.buffer, ptr, size);
+
+        view.setInt32(0, type.enum.indexOf(value.tag), true);
+        let addr = BYTES;
+        for (let [field, elm] of fields) {
+            view[setter(field)](addr, elm, true);
             addr += BYTES;
         }
         return ptr;
     }
 }
 
-export function concatBytes(bytes, is_float = false) {
+// Enum whose variant carries fields is a pointer to the object, instead of the tag
+function hasFields(type) {
+    return Object.values(type.fields ?? {}).some((fields) => fields.length > 0);
+}
+
+// Slot has the 32-bit types in its lower half
+export function concatBytes(bytes, type = "int") {
     const buffer = new ArrayBuffer(8);
     const view = new DataView(buffer);
     let index = 0;
@@ -108,5 +175,5 @@ export function concatBytes(bytes, is_float = false) {
         view.setUint8(index, byte);
         index += 1;
     }
-    return is_float ? view.getFloat32(0, true) : view.getInt32(0, true);
+    return view[GETTERS[type] ?? "getInt32"](0, true);
 }
@@ -4,7 +4,7 @@ import { read } from "./ffi.mjs";
 
 export async function lumo(code) {
     const result = compile(code);
-    const returnType = eval(result.return_type());
+    const { type: returnType, aliases } = JSON.parse(result.return_type());
     const bytecodes = result.bytecode().buffer;
 
     const stdlib = new LumoNodeLib();
@@ -15,6 +15,6 @@ export async function lumo(code) {
 
     const raw = instance.exports._start();
     if (returnType !== "void") {
-        return read(instance, returnType, raw);
+        return read(instance, returnType, raw, aliases);
     }
 }
@@ -37,7 +37,7 @@ export class LumoStdLib {
             },
             arrlen: (addr) => {
                 let view = new Uint8Array(this.instance.exports.mem.buffer);
-                return concatBytes(view.slice(addr, addr + 4), false);
+                return concatBytes(view.slice(addr, addr + 4));
             },
             join: (array, delimiter) => {
                 array = this.reads({ type: "array", element: "str" }, array);
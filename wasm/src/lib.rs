@@ -1,10 +1,12 @@
 use lumo_core::{Compiler, Type};
+use serde_json::{Map, Value, json};
 use wasm_bindgen::prelude::wasm_bindgen;
 
 #[wasm_bindgen]
 pub struct Lumo {
     bytecode: Vec<u8>,
     return_type: String,
+    summary: String,
 }
 
 #[wasm_bindgen]
@@ -18,6 +20,12 @@ impl Lumo {
     pub fn return_type(&self) -> String {
         self.return_type.clone()
     }
+
+    /// Type inference summary in JSON
+    #[wasm_bindgen]
+    pub fn summary(&self) -> String {
+        self.summary.clone()
+    }
 }
 
 #[wasm_bindgen]
@@ -27,37 +35,102 @@ pub fn lumo(source: &str) -> Result<Lumo, String> {
         let bytes = wat::parse_str(wat_code.clone()).unwrap();
         Ok(Lumo {
             bytecode: bytes,
-            return_type: type_to_json(&compiler.result),
+            return_type: return_type_json(&compiler),
+            summary: serde_json::to_string(&compiler.summary()).unwrap_or_default(),
         })
     } else {
-        let error_message = "failed to parse, compile or type check";
-        Err(compiler.error.unwrap_or(error_message.to_string()))
+        let errors = compiler.errors.iter();
+        let errors = errors.map(|err| err.render(source, "input"));
+        Err(errors.collect::<Vec<_>>().join("\n\n"))
+    }
+}
+
+/// Format the source code into the canonical style
+#[wasm_bindgen]
+pub fn format(source: &str) -> Result<String, String> {
+    lumo_core::format(source).map_err(|errors| {
+        let errors = errors.iter().map(|err| err.render(source, "input"));
+        errors.collect::<Vec<_>>().join("\n\n")
+    })
+}
+
+/// Descriptor of the return value in JSON, with the definitions of type aliases
+/// that the type refers to by name, so that recursive types can be decoded
+pub fn return_type_json(compiler: &Compiler) -> String {
+    let aliases = compiler
+        .alias
+        .iter()
+        .filter(|(name, _)| **name != Type::Any.format() && !compiler.generic.contains_key(*name));
+    let mut aliases: Map<_, _> = aliases
+        .map(|(name, typ)| (name.clone(), type_to_json(typ)))
+        .collect();
+    instances(&compiler.result, compiler, &mut aliases);
+    let descriptor = json!({ "type": type_to_json(&compiler.result), "aliases": aliases });
+    descriptor.to_string()
+}
+
+/// Generic aliases with the type arguments that the type uses, by the names like `List<int>`
+fn instances(typ: &Type, compiler: &Compiler, aliases: &mut Map<String, Value>) {
+    match typ {
+        Type::Generic(..) if !aliases.contains_key(&typ.format()) => {
+            let Some(solved) = typ.solve_alias(&mut compiler.clone(), vec![]) else {
+                return;
+            };
+            aliases.insert(typ.format(), type_to_json(&solved));
+            instances(&solved, compiler, aliases);
+        }
+        Type::Array(typ) | Type::Nullable(typ) => instances(typ, compiler, aliases),
+        Type::Dict(dict) => dict
+            .values()
+            .for_each(|typ| instances(typ, compiler, aliases)),
+        Type::Enum(variants) => variants
+            .values()
+            .flatten()
+            .for_each(|typ| instances(typ, compiler, aliases)),
+        Type::Function(args, ret) => {
+            args.iter()
+                .for_each(|typ| instances(typ, compiler, aliases));
+            instances(ret, compiler, aliases);
+        }
+        _ => {}
     }
 }
 
-pub fn type_to_json(typ: &Type) -> String {
+pub fn type_to_json(typ: &Type) -> Value {
     match typ {
-        Type::Integer => "\"int\"".to_string(),
-        Type::Number => "\"num\"".to_string(),
-        Type::Bool => "\"bool\"".to_string(),
-        Type::String => "\"str\"".to_string(),
-        Type::Void => "\"void\"".to_string(),
-        Type::Any => "\"any\"".to_string(),
-        Type::Dict(dict) => format!(
-            "({{ type: \"dict\", fields: {{ {} }} }})",
-            dict.iter()
-                .map(|(k, typ)| format!("{k}: {}", type_to_json(typ)))
-                .collect::<Vec<_>>()
-                .join(", ")
-        ),
-        Type::Array(typ) => format!("({{ type: \"array\", element: {} }})", type_to_json(typ)),
-        Type::Enum(e) => format!(
-            "({{ type: \"enum\", enum: [{}] }})",
-            e.iter()
-                .map(|x| format!("\"{x}\""))
-                .collect::<Vec<_>>()
-                .join(", ")
-        ),
-        Type::Alias(name) => format!("({{ type: \"alias\", name: \"{name}\" }})"),
+        Type::Integer => json!("int"),
+        Type::Number => json!("num"),
+        Type::Long => json!("long"),
+        Type::Double => json!("double"),
+        Type::Unsigned => json!("uint"),
+        Type::Bool => json!("bool"),
+        Type::String => json!("str"),
+        Type::Void => json!("void"),
+        Type::Any => json!("any"),
+        Type::Dict(dict) => {
+            let fields: Map<_, _> = dict
+                .iter()
+                .map(|(key, typ)| (key.clone(), type_to_json(typ)))
+                .collect();
+            json!({ "type": "dict", "fields": fields })
+        }
+        Type::Array(typ) => json!({ "type": "array", "element": type_to_json(typ) }),
+        Type::Enum(variants) => {
+            let fields: Map<_, _> = variants
+                .iter()
+                .map(|(key, fields)| (key.clone(), fields.iter().map(type_to_json).collect()))
+                .collect();
+            let keys: Vec<_> = variants.keys().collect();
+            json!({ "type": "enum", "enum": keys, "fields": fields })
+        }
+        Type::Function(args, ret) => {
+            let args: Vec<_> = args.iter().map(type_to_json).collect();
+            json!({ "type": "function", "arguments": args, "returns": type_to_json(ret) })
+        }
+        Type::Alias(name) => json!({ "type": "alias", "name": name }),
+        Type::Generic(..) => json!({ "type": "alias", "name": typ.format() }),
+        Type::Param(_) | Type::Var(_) => json!("any"),
+        // Null is decoded the same as the pointer type itself
+        Type::Nullable(typ) => type_to_json(typ),
     }
 }